@@ -0,0 +1,638 @@
+use crate::{download::Metadata, state::state_dir};
+
+use {
+    chrono::{DateTime, Local, Utc},
+    don_error::*,
+    rusqlite::{params, Connection, OptionalExtension, Row},
+    std::{
+        collections::HashMap,
+        fs::read,
+        path::{absolute, Path, PathBuf},
+    },
+};
+
+const DATABASE_FILE: &str = "catalogue.sqlite";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS wallpapers (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE,
+        hash TEXT,
+        source_url TEXT,
+        image_url TEXT,
+        site TEXT,
+        bookmark_title TEXT,
+        downloaded_at TEXT,
+        width INTEGER,
+        height INTEGER,
+        aspect_class TEXT
+    );
+    CREATE TABLE IF NOT EXISTS displays (
+        wallpaper_id INTEGER NOT NULL REFERENCES wallpapers(id) ON DELETE CASCADE,
+        displayed_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS displays_wallpaper_id ON displays(wallpaper_id);
+    CREATE TABLE IF NOT EXISTS ratings (
+        wallpaper_id INTEGER PRIMARY KEY REFERENCES wallpapers(id) ON DELETE CASCADE,
+        stars INTEGER,
+        favourite INTEGER NOT NULL DEFAULT 0,
+        banned INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS credits (
+        wallpaper_id INTEGER PRIMARY KEY REFERENCES wallpapers(id) ON DELETE CASCADE,
+        title TEXT,
+        author TEXT,
+        licence TEXT
+    );
+    CREATE TABLE IF NOT EXISTS tags (
+        wallpaper_id INTEGER NOT NULL REFERENCES wallpapers(id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        UNIQUE (wallpaper_id, tag)
+    );
+";
+
+/// Everything we know about the wallpapers of the library, kept in a SQLite database so that it
+/// survives the deletion of the bookmarks.
+pub(crate) struct Catalogue {
+    connection: Connection,
+}
+
+#[derive(Debug)]
+pub(crate) struct Wallpaper {
+    pub(crate) path: String,
+    pub(crate) hash: Option<String>,
+    pub(crate) source_url: Option<String>,
+    pub(crate) image_url: Option<String>,
+    pub(crate) site: Option<String>,
+    pub(crate) bookmark_title: Option<String>,
+    pub(crate) downloaded_at: Option<DateTime<Utc>>,
+    pub(crate) width: Option<u32>,
+    pub(crate) height: Option<u32>,
+    pub(crate) aspect_class: Option<String>,
+    pub(crate) title: Option<String>,
+    pub(crate) author: Option<String>,
+    pub(crate) licence: Option<String>,
+    pub(crate) nb_displays: u32,
+    pub(crate) last_displayed_at: Option<DateTime<Utc>>,
+}
+
+pub(crate) struct NewDownload<'l> {
+    pub(crate) path: &'l Path,
+    pub(crate) hash: &'l str,
+    pub(crate) source_url: &'l str,
+    pub(crate) image_url: &'l str,
+    pub(crate) site: &'l str,
+    /// None when not downloaded from a bookmark
+    pub(crate) bookmark_title: Option<&'l str>,
+    /// Found on the page of the wallpaper
+    pub(crate) metadata: &'l Metadata,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+/// How much we like a wallpaper, which follows it when it's moved.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Rating {
+    /// From 1 to 5
+    pub(crate) stars: Option<u8>,
+    pub(crate) favourite: bool,
+    pub(crate) banned: bool,
+}
+
+const SELECT_WALLPAPERS: &str = "
+    SELECT path, hash, source_url, image_url, site, bookmark_title, downloaded_at, width, height,
+        aspect_class, title, author, licence, COUNT(displays.wallpaper_id),
+        MAX(displays.displayed_at)
+    FROM wallpapers
+    LEFT JOIN credits ON credits.wallpaper_id = wallpapers.id
+    LEFT JOIN displays ON displays.wallpaper_id = wallpapers.id
+";
+
+impl Catalogue {
+    pub(crate) fn open() -> DonResult<Self> {
+        Self::from_connection(Connection::open(state_dir()?.join(DATABASE_FILE))?)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn open_in_memory() -> DonResult<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> DonResult<Self> {
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Catalogue { connection })
+    }
+
+    pub(crate) fn record_download(&self, download: &NewDownload) -> DonResult<()> {
+        self.connection.execute(
+            "INSERT INTO wallpapers (path, hash, source_url, image_url, site, bookmark_title,
+                downloaded_at, width, height)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT(path) DO UPDATE SET hash = ?2, source_url = ?3, image_url = ?4, site = ?5,
+                bookmark_title = ?6, downloaded_at = ?7, width = ?8, height = ?9,
+                aspect_class = NULL",
+            params![
+                path_to_str(download.path)?,
+                download.hash,
+                download.source_url,
+                download.image_url,
+                download.site,
+                download.bookmark_title,
+                Utc::now(),
+                download.width,
+                download.height,
+            ],
+        )?;
+        let id = self.ensure(download.path)?;
+        let metadata = download.metadata;
+        self.connection.execute(
+            "INSERT INTO credits (wallpaper_id, title, author, licence) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(wallpaper_id) DO UPDATE SET title = ?2, author = ?3, licence = ?4",
+            params![id, metadata.title, metadata.author, metadata.licence],
+        )?;
+        self.add_tags(download.path, &metadata.tags)
+    }
+
+    /// Records that a file was moved, when it has been sorted or moved back to the root dir.
+    pub(crate) fn record_move(&self, old_path: &Path, new_path: &Path) -> DonResult<()> {
+        let (old_path, new_path) = (path_to_str(old_path)?, path_to_str(new_path)?);
+        if old_path == new_path {
+            return Ok(());
+        }
+        // A file that has been deleted outside of the manager may have left its row behind
+        self.connection
+            .execute("DELETE FROM wallpapers WHERE path = ?1", [new_path])?;
+        self.connection.execute(
+            "UPDATE wallpapers SET path = ?1 WHERE path = ?2",
+            [new_path, old_path],
+        )?;
+        Ok(())
+    }
+
+    /// Records that a file was deleted, forgetting its ratings, tags and displays along with it.
+    pub(crate) fn record_removal(&self, path: &Path) -> DonResult<()> {
+        self.connection.execute(
+            "DELETE FROM wallpapers WHERE path = ?1",
+            [path_to_str(path)?],
+        )?;
+        Ok(())
+    }
+
+    /// Records the classification of a file by the sort, adding it to the catalogue if it wasn't
+    /// downloaded by the manager.
+    pub(crate) fn record_sorted(
+        &self,
+        path: &Path,
+        width: u32,
+        height: u32,
+        aspect_class: &str,
+    ) -> DonResult<()> {
+        let path_str = path_to_str(path)?;
+        let nb_updated = self.connection.execute(
+            "UPDATE wallpapers SET width = ?1, height = ?2, aspect_class = ?3 WHERE path = ?4",
+            params![width, height, aspect_class, path_str],
+        )?;
+        if nb_updated == 0 {
+            self.connection.execute(
+                "INSERT INTO wallpapers (path, hash, width, height, aspect_class)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    path_str,
+                    sha256::digest(&read(path)?),
+                    width,
+                    height,
+                    aspect_class
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn record_display(&self, path: &Path) -> DonResult<()> {
+        let path = path_to_str(path)?;
+        self.connection.execute(
+            "INSERT OR IGNORE INTO wallpapers (path) VALUES (?1)",
+            [path],
+        )?;
+        self.connection.execute(
+            "INSERT INTO displays (wallpaper_id, displayed_at)
+            SELECT id, ?1 FROM wallpapers WHERE path = ?2",
+            params![Utc::now(), path],
+        )?;
+        Ok(())
+    }
+
+    /// Adds `path` to the catalogue if it isn't in it yet, so that it can be rated or tagged.
+    fn ensure(&self, path: &Path) -> DonResult<i64> {
+        let path = path_to_str(path)?;
+        self.connection.execute(
+            "INSERT OR IGNORE INTO wallpapers (path) VALUES (?1)",
+            [path],
+        )?;
+        Ok(self.connection.query_row(
+            "SELECT id FROM wallpapers WHERE path = ?1",
+            [path],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Changes the rating of `path`, starting from the one it already has.
+    pub(crate) fn update_rating(
+        &self,
+        path: &Path,
+        update: impl FnOnce(&mut Rating),
+    ) -> DonResult<()> {
+        let id = self.ensure(path)?;
+        let mut rating = self.rating(path)?.unwrap_or_default();
+        update(&mut rating);
+        self.connection.execute(
+            "INSERT INTO ratings (wallpaper_id, stars, favourite, banned) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(wallpaper_id) DO UPDATE SET stars = ?2, favourite = ?3, banned = ?4",
+            params![id, rating.stars, rating.favourite, rating.banned],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn rating(&self, path: &Path) -> DonResult<Option<Rating>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT stars, favourite, banned FROM ratings
+                JOIN wallpapers ON ratings.wallpaper_id = wallpapers.id
+                WHERE path = ?1",
+                [path_to_str(path)?],
+                Rating::from_row,
+            )
+            .optional()?)
+    }
+
+    /// The rating of every rated wallpaper, by path.
+    pub(crate) fn ratings(&self) -> DonResult<HashMap<PathBuf, Rating>> {
+        let mut statement = self.connection.prepare(
+            "SELECT stars, favourite, banned, path FROM ratings
+            JOIN wallpapers ON ratings.wallpaper_id = wallpapers.id",
+        )?;
+        let ratings = statement
+            .query_map([], |row| {
+                Ok((
+                    PathBuf::from(row.get::<_, String>(3)?),
+                    Rating::from_row(row)?,
+                ))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(ratings)
+    }
+
+    pub(crate) fn add_tags(&self, path: &Path, tags: &[String]) -> DonResult<()> {
+        let id = self.ensure(path)?;
+        for tag in tags {
+            self.connection.execute(
+                "INSERT OR IGNORE INTO tags (wallpaper_id, tag) VALUES (?1, ?2)",
+                params![id, tag],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn remove_tags(&self, path: &Path, tags: &[String]) -> DonResult<()> {
+        for tag in tags {
+            self.connection.execute(
+                "DELETE FROM tags WHERE tag = ?1
+                AND wallpaper_id IN (SELECT id FROM wallpapers WHERE path = ?2)",
+                params![tag, path_to_str(path)?],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn tags(&self, path: &Path) -> DonResult<Vec<String>> {
+        let mut statement = self.connection.prepare(
+            "SELECT tag FROM tags
+            JOIN wallpapers ON tags.wallpaper_id = wallpapers.id
+            WHERE path = ?1
+            ORDER BY tag",
+        )?;
+        let tags = statement
+            .query_map([path_to_str(path)?], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+
+    /// The last `limit` wallpapers displayed, most recent first.
+    pub(crate) fn recent_displays(&self, limit: usize) -> DonResult<Vec<PathBuf>> {
+        let mut statement = self.connection.prepare(
+            "SELECT path FROM displays
+            JOIN wallpapers ON displays.wallpaper_id = wallpapers.id
+            ORDER BY displayed_at DESC, displays.rowid DESC
+            LIMIT ?1",
+        )?;
+        let paths = statement
+            .query_map([limit], |row| row.get::<_, String>(0))?
+            .map(|path| path.map(PathBuf::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(paths)
+    }
+
+    pub(crate) fn find(&self, path: &Path) -> DonResult<Option<Wallpaper>> {
+        Ok(self
+            .connection
+            .query_row(
+                &format!("{SELECT_WALLPAPERS} WHERE path = ?1 GROUP BY wallpapers.id"),
+                [path_to_str(path)?],
+                Wallpaper::from_row,
+            )
+            .optional()?)
+    }
+
+    pub(crate) fn list(
+        &self,
+        site: Option<&str>,
+        aspect_class: Option<&str>,
+        tag: Option<&str>,
+    ) -> DonResult<Vec<Wallpaper>> {
+        let mut statement = self.connection.prepare(&format!(
+            "{SELECT_WALLPAPERS}
+            WHERE (?1 IS NULL OR site = ?1) AND (?2 IS NULL OR aspect_class = ?2)
+                AND (?3 IS NULL OR wallpapers.id IN (SELECT wallpaper_id FROM tags WHERE tag = ?3))
+            GROUP BY wallpapers.id
+            ORDER BY path"
+        ))?;
+        let wallpapers = statement
+            .query_map(params![site, aspect_class, tag], Wallpaper::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(wallpapers)
+    }
+}
+
+impl Wallpaper {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Wallpaper {
+            path: row.get(0)?,
+            hash: row.get(1)?,
+            source_url: row.get(2)?,
+            image_url: row.get(3)?,
+            site: row.get(4)?,
+            bookmark_title: row.get(5)?,
+            downloaded_at: row.get(6)?,
+            width: row.get(7)?,
+            height: row.get(8)?,
+            aspect_class: row.get(9)?,
+            title: row.get(10)?,
+            author: row.get(11)?,
+            licence: row.get(12)?,
+            nb_displays: row.get(13)?,
+            last_displayed_at: row.get(14)?,
+        })
+    }
+}
+
+impl Rating {
+    /// From the `stars, favourite, banned` columns.
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Rating {
+            stars: row.get(0)?,
+            favourite: row.get(1)?,
+            banned: row.get(2)?,
+        })
+    }
+}
+
+fn path_to_str(path: &Path) -> DonResult<&str> {
+    path.to_str()
+        .ok_or_don_err(format!("{path:?} is not valid unicode"))
+}
+
+fn format_date(date: &Option<DateTime<Utc>>) -> String {
+    match date {
+        Some(date) => date
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// Prints everything the catalogue knows about a file.
+pub fn show(path: &Path) -> DonResult<()> {
+    let catalogue = Catalogue::open()?;
+    let path = absolute(path)?;
+    let wallpaper = catalogue
+        .find(&path)?
+        .ok_or_don_err(format!("{path:?} is not in the catalogue"))?;
+    let or_dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    println!("Path           : {}", wallpaper.path);
+    println!(
+        "Dimensions     : {}x{} ({})",
+        wallpaper.width.unwrap_or_default(),
+        wallpaper.height.unwrap_or_default(),
+        or_dash(&wallpaper.aspect_class)
+    );
+    println!("Site           : {}", or_dash(&wallpaper.site));
+    println!("Source url     : {}", or_dash(&wallpaper.source_url));
+    println!("Image url      : {}", or_dash(&wallpaper.image_url));
+    println!("Bookmark title : {}", or_dash(&wallpaper.bookmark_title));
+    println!("Title          : {}", or_dash(&wallpaper.title));
+    println!("Author         : {}", or_dash(&wallpaper.author));
+    println!("Licence        : {}", or_dash(&wallpaper.licence));
+    println!("Downloaded at  : {}", format_date(&wallpaper.downloaded_at));
+    println!("Hash           : {}", or_dash(&wallpaper.hash));
+    println!(
+        "Displayed      : {} time(s), last at {}",
+        wallpaper.nb_displays,
+        format_date(&wallpaper.last_displayed_at)
+    );
+    let rating = catalogue.rating(&path)?.unwrap_or_default();
+    println!(
+        "Rating         : {}{}{}",
+        rating
+            .stars
+            .map(|stars| format!("{stars}/5"))
+            .unwrap_or_else(|| "-".to_string()),
+        if rating.favourite { ", favourite" } else { "" },
+        if rating.banned { ", banned" } else { "" }
+    );
+    println!("Tags           : {}", catalogue.tags(&path)?.join(", "));
+    Ok(())
+}
+
+/// Prints the wallpapers of the catalogue, optionally filtered by site, aspect class and tag.
+pub fn list(site: Option<&str>, aspect_class: Option<&str>, tag: Option<&str>) -> DonResult<()> {
+    let wallpapers = Catalogue::open()?.list(site, aspect_class, tag)?;
+    for wallpaper in &wallpapers {
+        println!(
+            "{}\t{}x{}\t{}\t{}",
+            wallpaper.path,
+            wallpaper.width.unwrap_or_default(),
+            wallpaper.height.unwrap_or_default(),
+            wallpaper.site.as_deref().unwrap_or("-"),
+            wallpaper.source_url.as_deref().unwrap_or("-"),
+        );
+    }
+    println!("{} wallpaper(s)", wallpapers.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::LazyLock;
+
+    static METADATA: LazyLock<Metadata> = LazyLock::new(|| Metadata {
+        title: None,
+        author: Some("jdoe".to_string()),
+        licence: None,
+        tags: vec!["nature".to_string(), "landscape".to_string()],
+    });
+
+    fn download(path: &Path) -> NewDownload<'_> {
+        NewDownload {
+            path,
+            hash: "abc",
+            source_url: "https://wallhaven.cc/w/zy3l5o",
+            image_url: "https://w.wallhaven.cc/full/zy/wallhaven-zy3l5o.jpg",
+            site: "wallhaven.cc",
+            bookmark_title: Some("Forest, river, sunset"),
+            metadata: &METADATA,
+            width: 3840,
+            height: 2160,
+        }
+    }
+
+    #[test]
+    fn test_download_then_sort() {
+        let catalogue = Catalogue::open_in_memory().unwrap();
+        let downloaded_path = PathBuf::from("/wallpapers/zy3l5o.jpg");
+        let sorted_path = PathBuf::from("/wallpapers/single/zy3l5o.jpg");
+        catalogue
+            .record_download(&download(&downloaded_path))
+            .unwrap();
+        catalogue
+            .record_move(&downloaded_path, &sorted_path)
+            .unwrap();
+        catalogue
+            .record_sorted(&sorted_path, 3840, 2160, "single")
+            .unwrap();
+
+        assert!(catalogue.find(&downloaded_path).unwrap().is_none());
+        let wallpaper = catalogue.find(&sorted_path).unwrap().unwrap();
+        assert_eq!(wallpaper.site.as_deref(), Some("wallhaven.cc"));
+        assert_eq!(wallpaper.aspect_class.as_deref(), Some("single"));
+        assert_eq!(wallpaper.author.as_deref(), Some("jdoe"));
+        assert_eq!(
+            catalogue.tags(&sorted_path).unwrap(),
+            ["landscape", "nature"]
+        );
+        assert_eq!(wallpaper.nb_displays, 0);
+        assert!(wallpaper.downloaded_at.is_some());
+    }
+
+    #[test]
+    fn test_list_filters() {
+        let catalogue = Catalogue::open_in_memory().unwrap();
+        let (single, dual) = (
+            PathBuf::from("/wallpapers/single/a.jpg"),
+            PathBuf::from("/wallpapers/dual/b.jpg"),
+        );
+        catalogue.record_download(&download(&single)).unwrap();
+        catalogue.record_download(&download(&dual)).unwrap();
+        catalogue
+            .record_sorted(&single, 3840, 2160, "single")
+            .unwrap();
+        catalogue.record_sorted(&dual, 7680, 2160, "dual").unwrap();
+
+        assert_eq!(catalogue.list(None, None, None).unwrap().len(), 2);
+        assert_eq!(
+            catalogue
+                .list(Some("wallhaven.cc"), Some("dual"), None)
+                .unwrap()[0]
+                .path,
+            "/wallpapers/dual/b.jpg"
+        );
+        assert!(catalogue
+            .list(Some("flickr.com"), None, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_display_history() {
+        let catalogue = Catalogue::open_in_memory().unwrap();
+        let (known, unknown) = (
+            PathBuf::from("/wallpapers/single/a.jpg"),
+            PathBuf::from("/wallpapers/single/b.jpg"),
+        );
+        catalogue.record_download(&download(&known)).unwrap();
+        for path in [&known, &unknown, &known] {
+            catalogue.record_display(path).unwrap();
+        }
+
+        assert_eq!(
+            catalogue.recent_displays(2).unwrap(),
+            vec![known.clone(), unknown.clone()]
+        );
+        assert_eq!(catalogue.find(&known).unwrap().unwrap().nb_displays, 2);
+        assert_eq!(catalogue.find(&unknown).unwrap().unwrap().nb_displays, 1);
+    }
+
+    #[test]
+    fn test_ratings_and_tags_follow_moves() {
+        let catalogue = Catalogue::open_in_memory().unwrap();
+        let (downloaded, sorted) = (
+            PathBuf::from("/wallpapers/a.jpg"),
+            PathBuf::from("/wallpapers/single/a.jpg"),
+        );
+        catalogue
+            .update_rating(&downloaded, |rating| rating.stars = Some(4))
+            .unwrap();
+        catalogue
+            .update_rating(&downloaded, |rating| rating.banned = true)
+            .unwrap();
+        catalogue
+            .add_tags(&downloaded, &["snow".to_string(), "night".to_string()])
+            .unwrap();
+        catalogue.record_move(&downloaded, &sorted).unwrap();
+
+        assert_eq!(
+            catalogue.ratings().unwrap(),
+            HashMap::from([(
+                sorted.clone(),
+                Rating {
+                    stars: Some(4),
+                    favourite: false,
+                    banned: true,
+                }
+            )])
+        );
+        catalogue
+            .remove_tags(&sorted, &["night".to_string()])
+            .unwrap();
+        assert_eq!(catalogue.tags(&sorted).unwrap(), ["snow"]);
+        assert_eq!(
+            catalogue.list(None, None, Some("snow")).unwrap()[0].path,
+            "/wallpapers/single/a.jpg"
+        );
+        assert!(catalogue
+            .list(None, None, Some("night"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_removal_forgets_ratings_and_tags() {
+        let catalogue = Catalogue::open_in_memory().unwrap();
+        let path = PathBuf::from("/wallpapers/a.jpg");
+        catalogue
+            .update_rating(&path, |rating| rating.favourite = true)
+            .unwrap();
+        catalogue.add_tags(&path, &["snow".to_string()]).unwrap();
+        catalogue.record_display(&path).unwrap();
+        catalogue.record_removal(&path).unwrap();
+
+        assert!(catalogue.find(&path).unwrap().is_none());
+        assert!(catalogue.ratings().unwrap().is_empty());
+        assert!(catalogue.recent_displays(1).unwrap().is_empty());
+        assert!(catalogue.tags(&path).unwrap().is_empty());
+    }
+}
@@ -1,11 +1,74 @@
+use crate::{
+    download::{Flickr, Imgur, NamingStrategy, ScrapingRule, Unsplash},
+    setters::SetterKind,
+    wallpapers::{buckets::Bucket, fit::FitStrategy, quarantine::Quarantine},
+};
+
 use firefox_sync_sdk::Client as FirefoxSyncClient;
 
 #[derive(Debug, serde::Deserialize)]
 pub(crate) struct Config {
     pub(crate) wallpapers_dir: String,
+    /// Dirs of the default buckets, when `buckets` isn't set
+    #[serde(default = "default_single_screen_dir")]
     pub(crate) single_screen_dir: String,
+    #[serde(default = "default_dual_screen_dir")]
     pub(crate) dual_screen_dir: String,
+    /// How wallpapers are sorted, in order of priority
+    #[serde(default)]
+    buckets: Vec<Bucket>,
     pub(crate) firefox_sync_client: FirefoxSyncClient,
+    #[serde(default)]
+    pub(crate) scraping_rules: Vec<ScrapingRule>,
+    /// Of the wallhaven.cc account, for NSFW wallpapers and the account's search settings
+    #[serde(default)]
+    pub(crate) wallhaven_api_key: Option<String>,
+    #[serde(default)]
+    pub(crate) flickr: Flickr,
+    #[serde(default)]
+    pub(crate) imgur: Imgur,
+    #[serde(default)]
+    pub(crate) unsplash: Unsplash,
+    #[serde(default = "default_download_workers")]
+    pub(crate) download_workers: usize,
+    #[serde(default)]
+    pub(crate) naming_strategy: NamingStrategy,
+    /// How many of the last displayed wallpapers are avoided when picking new ones
+    #[serde(default = "default_recently_displayed_to_avoid")]
+    pub(crate) recently_displayed_to_avoid: usize,
+    /// Detected from the session when not set
+    #[serde(default)]
+    pub(crate) wallpaper_setter: Option<SetterKind>,
+    /// How wallpapers are fitted to the monitors before being displayed, left to the setter by default
+    #[serde(default)]
+    pub(crate) fit_strategy: FitStrategy,
+    #[serde(default)]
+    pub(crate) quarantine: Quarantine,
+}
+
+impl Config {
+    pub(crate) fn buckets(&self) -> Vec<Bucket> {
+        match self.buckets.is_empty() {
+            true => Bucket::defaults(&self.single_screen_dir, &self.dual_screen_dir),
+            false => self.buckets.clone(),
+        }
+    }
+}
+
+fn default_single_screen_dir() -> String {
+    "single".to_string()
+}
+
+fn default_dual_screen_dir() -> String {
+    "dual".to_string()
+}
+
+fn default_download_workers() -> usize {
+    4
+}
+
+fn default_recently_displayed_to_avoid() -> usize {
+    10
 }
 
 config_helpers::config!("wallpapers_mgr");
@@ -0,0 +1,114 @@
+mod server;
+
+pub use server::run;
+
+use crate::{state::state_dir, wallpapers::change::Mode};
+
+use {
+    don_error::*,
+    serde::{Deserialize, Serialize},
+    std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixStream,
+        path::{Path, PathBuf},
+    },
+};
+
+/// What the `wall` CLI asks the running daemon, as a line of JSON on its socket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Changes the wallpapers now, from `bucket` only if set
+    Next {
+        bucket: Option<String>,
+    },
+    /// Displays the previous wallpapers again
+    Previous,
+    /// Stops changing the wallpapers
+    Pause,
+    Resume,
+    SetInterval {
+        minutes: u64,
+    },
+    SetMode {
+        mode: Mode,
+    },
+    Status,
+    /// Restarts the daemon, which reads the config again
+    ReloadConfig,
+}
+
+/// Answer of the daemon, also as a line of JSON : a message for the user, or an error.
+type Response = Result<String, String>;
+
+fn socket_path() -> DonResult<PathBuf> {
+    Ok(state_dir()?.join("daemon.sock"))
+}
+
+/// Sends `request` to the running daemon and returns its answer, or `None` if no daemon is running.
+pub(crate) fn send(request: &Request) -> DonResult<Option<String>> {
+    send_to(&socket_path()?, request)
+}
+
+fn send_to(path: &Path, request: &Request) -> DonResult<Option<String>> {
+    let Ok(mut stream) = UnixStream::connect(path) else {
+        return Ok(None);
+    };
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    match serde_json::from_str::<Response>(&line)? {
+        Ok(message) => Ok(Some(message)),
+        Err(err) => bail!("{err}"),
+    }
+}
+
+/// Sends `request` to the running daemon and prints its answer.
+pub fn control(request: Request) -> DonResult<()> {
+    match send(&request)? {
+        Some(message) => println!("{message}"),
+        None => bail!("No daemon running, start one with `wall daemon`"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use {
+        server::{respond, serve},
+        std::{os::unix::net::UnixListener, sync::mpsc::channel, thread},
+    };
+
+    #[test]
+    fn test_request_round_trip() {
+        let path = std::env::temp_dir().join(format!("{}_daemon.sock", std::process::id()));
+        assert_eq!(send_to(&path, &Request::Status).unwrap(), None);
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let (sender, receiver) = channel();
+        thread::spawn(move || serve(listener, sender));
+        thread::spawn(move || {
+            for (request, stream) in receiver {
+                let response = match request {
+                    Request::SetInterval { minutes: 0 } => Err("Too short".to_string()),
+                    request => Ok(format!("{request:?}")),
+                };
+                respond(stream, response);
+            }
+        });
+        assert_eq!(
+            send_to(
+                &path,
+                &Request::SetMode {
+                    mode: Mode::OnlyDual
+                }
+            )
+            .unwrap(),
+            Some("SetMode { mode: OnlyDual }".to_string())
+        );
+        assert!(send_to(&path, &Request::SetInterval { minutes: 0 }).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+use crate::wallpapers::{
+    change::{back, change, Mode},
+    history::{Entry, History},
+    watch,
+};
+
+use {
+    super::{send_to, socket_path, Request, Response},
+    clap::ValueEnum,
+    don_error::*,
+    std::{
+        env::current_exe,
+        fs::remove_file,
+        io::{BufRead, BufReader, Write},
+        os::unix::{
+            net::{UnixListener, UnixStream},
+            process::CommandExt,
+        },
+        process::Command,
+        sync::mpsc::{channel, RecvTimeoutError, Sender},
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+/// Changes the wallpapers every `minutes`, and answers the requests of the `wall` CLI meanwhile.
+/// With `watch`, also sorts the new files.
+pub fn run(
+    minutes: u64,
+    mode: Mode,
+    bucket: Option<String>,
+    watch: bool,
+    paused: bool,
+) -> DonResult<()> {
+    let path = socket_path()?;
+    if send_to(&path, &Request::Status)?.is_some() {
+        bail!("A daemon is already running");
+    }
+    if path.exists() {
+        // Left by a daemon that didn't stop cleanly
+        remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    let (sender, receiver) = channel();
+    thread::spawn(move || serve(listener, sender));
+    if watch {
+        watch::spawn();
+    }
+
+    let mut daemon = Daemon::new(minutes, mode, bucket, paused);
+    loop {
+        let received = match daemon.paused {
+            true => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            false => {
+                receiver.recv_timeout(daemon.next_change.saturating_duration_since(Instant::now()))
+            }
+        };
+        match received {
+            Ok((Request::ReloadConfig, stream)) => {
+                respond(stream, Ok("Restarting to reload the config".to_string()));
+                remove_file(&path)?;
+                let err = Command::new(current_exe()?).args(daemon.args(watch)).exec();
+                return Err(err.into());
+            }
+            Ok((request, stream)) => {
+                let response = daemon.handle(request).map_err(|err| err.to_string());
+                respond(stream, response);
+            }
+            Err(RecvTimeoutError::Timeout) => try_or_report(|| daemon.change(None)),
+            Err(RecvTimeoutError::Disconnected) => bail!("Stopped listening on {path:?}"),
+        }
+    }
+}
+
+/// Reads the request of each client, and hands it to the main loop along with the stream to
+/// answer on.
+pub(super) fn serve(listener: UnixListener, requests: Sender<(Request, UnixStream)>) {
+    for stream in listener.incoming() {
+        try_or_report(|| {
+            let stream = stream?;
+            stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line)?;
+            match serde_json::from_str(&line) {
+                Ok(request) => requests.send((request, stream))?,
+                Err(err) => respond(stream, Err(format!("Invalid request : {err}"))),
+            }
+            Ok(())
+        });
+    }
+}
+
+pub(super) fn respond(mut stream: UnixStream, response: Response) {
+    try_or_report(|| {
+        writeln!(stream, "{}", serde_json::to_string(&response)?)?;
+        Ok(())
+    });
+}
+
+struct Daemon {
+    interval: Duration,
+    mode: Mode,
+    /// Where wallpapers are taken from, if not from every bucket
+    bucket: Option<String>,
+    paused: bool,
+    next_change: Instant,
+}
+
+impl Daemon {
+    fn new(minutes: u64, mode: Mode, bucket: Option<String>, paused: bool) -> Self {
+        Daemon {
+            interval: Duration::from_secs(minutes * 60),
+            mode,
+            bucket,
+            paused,
+            // The wallpapers are changed as soon as it starts
+            next_change: Instant::now(),
+        }
+    }
+
+    fn handle(&mut self, request: Request) -> DonResult<String> {
+        Ok(match request {
+            Request::Next { bucket } => {
+                self.change(bucket.as_deref())?;
+                "Wallpapers changed".to_string()
+            }
+            Request::Previous => {
+                self.previous()?;
+                "Previous wallpapers displayed".to_string()
+            }
+            Request::Pause => {
+                self.paused = true;
+                "Paused".to_string()
+            }
+            Request::Resume => {
+                self.paused = false;
+                self.next_change = Instant::now() + self.interval;
+                "Resumed".to_string()
+            }
+            Request::SetInterval { minutes } => {
+                if minutes == 0 {
+                    bail!("The interval must be at least a minute");
+                }
+                self.interval = Duration::from_secs(minutes * 60);
+                self.next_change = Instant::now() + self.interval;
+                format!("Wallpapers now change every {minutes} min")
+            }
+            Request::SetMode { mode } => {
+                self.mode = mode;
+                format!("Mode set to {}", mode_name(&self.mode))
+            }
+            Request::Status => self.status(History::load()?.current()),
+            Request::ReloadConfig => bail!("The config is reloaded by the main loop"),
+        })
+    }
+
+    fn change(&mut self, bucket: Option<&str>) -> DonResult<()> {
+        // Even if it fails, so that it isn't retried right away
+        self.next_change = Instant::now() + self.interval;
+        change(&self.mode, bucket.or(self.bucket.as_deref()))?;
+        Ok(())
+    }
+
+    fn previous(&mut self) -> DonResult<()> {
+        back()?;
+        self.next_change = Instant::now() + self.interval;
+        Ok(())
+    }
+
+    fn status(&self, current: Option<&Entry>) -> String {
+        let mut status = match self.paused {
+            true => "Paused".to_string(),
+            false => {
+                let remaining = self
+                    .next_change
+                    .saturating_duration_since(Instant::now())
+                    .as_secs();
+                format!("Next change in {}m{:02}s", remaining / 60, remaining % 60)
+            }
+        };
+        status += &format!(
+            "\nEvery {} min, mode {}",
+            self.interval.as_secs() / 60,
+            mode_name(&self.mode)
+        );
+        if let Some(bucket) = &self.bucket {
+            status += &format!(", from {bucket}");
+        }
+        if let Some(current) = current {
+            status += "\nCurrent wallpapers :";
+            for shown in &current.wallpapers {
+                status += &format!(
+                    "\n  {} : {}",
+                    shown.monitors.join(", "),
+                    shown.path.display()
+                );
+            }
+        }
+        status
+    }
+
+    /// Arguments of `wall` that start a daemon in the same state.
+    fn args(&self, watch: bool) -> Vec<String> {
+        let mut args = vec![
+            "daemon".to_string(),
+            "--minutes".to_string(),
+            (self.interval.as_secs() / 60).to_string(),
+            "--mode".to_string(),
+            mode_name(&self.mode),
+        ];
+        if let Some(bucket) = &self.bucket {
+            args.extend(["--bucket".to_string(), bucket.clone()]);
+        }
+        if watch {
+            args.push("--watch".to_string());
+        }
+        if self.paused {
+            args.push("--paused".to_string());
+        }
+        args
+    }
+}
+
+fn mode_name(mode: &Mode) -> String {
+    mode.to_possible_value()
+        .expect("No mode is skipped")
+        .get_name()
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pause_and_settings() {
+        let mut daemon = Daemon::new(30, Mode::FiftyFifty, None, false);
+        daemon.handle(Request::Pause).unwrap();
+        daemon
+            .handle(Request::SetMode {
+                mode: Mode::OnlySingle,
+            })
+            .unwrap();
+        assert!(daemon.handle(Request::SetInterval { minutes: 0 }).is_err());
+        daemon.handle(Request::SetInterval { minutes: 10 }).unwrap();
+        assert_eq!(
+            daemon.status(None),
+            "Paused\nEvery 10 min, mode only-single"
+        );
+        // Restarting keeps the state
+        assert_eq!(
+            daemon.args(true),
+            [
+                "daemon",
+                "--minutes",
+                "10",
+                "--mode",
+                "only-single",
+                "--watch",
+                "--paused"
+            ]
+        );
+
+        daemon.handle(Request::Resume).unwrap();
+        assert!(daemon.status(None).starts_with("Next change in 9m5"));
+    }
+}
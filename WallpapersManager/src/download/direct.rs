@@ -0,0 +1,51 @@
+use super::{Downloader, HttpClient, Scraped};
+
+use {don_error::*, url::Url};
+
+const IMAGE_EXTENSIONS: [&str; 7] = ["jpg", "jpeg", "png", "webp", "gif", "bmp", "tiff"];
+
+/// Whether the url looks like the one of an image file, from its extension.
+pub(crate) fn is_image_url(url: &Url) -> bool {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|name| name.rsplit_once('.'))
+        .is_some_and(|(_, extension)| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|image_extension| extension.eq_ignore_ascii_case(image_extension))
+        })
+}
+
+/// Links straight to an image, on any site. They have no metadata.
+pub(crate) struct DirectImage;
+
+impl Downloader for DirectImage {
+    fn domains(&self) -> Vec<&str> {
+        vec![]
+    }
+
+    fn handles(&self, url: &Url) -> bool {
+        is_image_url(url)
+    }
+
+    fn scrape(&self, _client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped> {
+        Ok(Scraped {
+            image_url: page_url.to_string(),
+            metadata: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_image_url() {
+        let is_image = |url: &str| is_image_url(&Url::parse(url).unwrap());
+        assert!(is_image("https://example.com/wallpapers/forest.JPG?w=3840"));
+        assert!(is_image("https://i.redd.it/a1b2c3d4e5f6.png"));
+        assert!(!is_image("https://example.com/wallpapers/forest"));
+        assert!(!is_image("https://example.com/forest.html"));
+    }
+}
@@ -0,0 +1,246 @@
+use super::{
+    direct::DirectImage, flickr::Flickr, imgur::Imgur, reddit::Reddit, rules::ScrapingRule,
+    unsplash::Unsplash, wallhaven::Wallhaven, wallpaper_flare::WallpaperFlare, HttpClient,
+};
+
+use {don_error::*, scraper::Html, url::Url};
+
+/// What the page of a wallpaper tells about it, to credit its author and to list wallpapers by
+/// tag.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Metadata {
+    pub(crate) title: Option<String>,
+    pub(crate) author: Option<String>,
+    pub(crate) licence: Option<String>,
+    pub(crate) tags: Vec<String>,
+}
+
+/// The image found by a downloader, along with what its page tells about it.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Scraped {
+    pub(crate) image_url: String,
+    pub(crate) metadata: Metadata,
+}
+
+/// A wallpaper source, able to turn the url of a bookmarked page into the url of the image.
+pub(crate) trait Downloader: Sync {
+    /// Domains handled by this downloader, subdomains included (ex: "flickr.com").
+    fn domains(&self) -> Vec<&str>;
+
+    fn handles(&self, url: &Url) -> bool {
+        url.domain().is_some_and(|domain| {
+            self.domains()
+                .iter()
+                .any(|handled| domain == *handled || domain.ends_with(&format!(".{handled}")))
+        })
+    }
+
+    /// Urls of the wallpapers a bookmarked url stands for : itself, or the photos of an album.
+    fn expand(&self, _client: &dyn HttpClient, url: &str) -> DonResult<Vec<String>> {
+        Ok(vec![url.to_string()])
+    }
+
+    /// Rewrites the bookmarked url into the url of the first page to scrape.
+    fn page_url(&self, url: &str) -> String {
+        url.to_string()
+    }
+
+    fn fetch_page(&self, client: &dyn HttpClient, url: &str) -> DonResult<String> {
+        client.get_text(url)
+    }
+
+    /// Finds the url of the image, and its metadata when the pages have some.
+    fn scrape(&self, client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped>;
+
+    /// Why the wallpaper shouldn't be downloaded, if it shouldn't.
+    fn rejects(&self, _metadata: &Metadata) -> Option<String> {
+        None
+    }
+}
+
+/// Finds the first tag matching `selector_str` and returns the value of its attribute `attr`.
+pub(crate) fn extract_attr(source_code: &str, selector_str: &str, attr: &str) -> DonResult<String> {
+    let html = scraper::Html::parse_document(source_code);
+    let err_ctx = DonErrorContext::new().with_ser("source code", source_code);
+    let selector = selector(selector_str)?;
+
+    Ok(html
+        .select(&selector)
+        .next()
+        .ok_or_don_err(format!("Couldn't find any tag matching '{selector_str}'"))
+        .err_ctx(&err_ctx)?
+        .value()
+        .attr(attr)
+        .ok_or_don_err(format!(
+            "The tag '{selector_str}' was found but doesn't contain an attribute '{attr}'"
+        ))
+        .err_ctx(&err_ctx)?
+        .to_string())
+}
+
+fn selector(selector_str: &str) -> DonResult<scraper::Selector> {
+    scraper::Selector::parse(selector_str)
+        .map_err(|_| err_msg!("Failed to create the selector for '{selector_str}'"))
+}
+
+/// Returns the text of every tag matching `selector_str`, trimmed, skipping the empty ones.
+pub(crate) fn extract_texts(html: &Html, selector_str: &str) -> DonResult<Vec<String>> {
+    Ok(html
+        .select(&selector(selector_str)?)
+        .map(|element| element.text().collect::<String>().trim().to_string())
+        .filter(|text| !text.is_empty())
+        .collect())
+}
+
+/// Returns the attribute `attr` of every tag matching `selector_str` that has it.
+pub(crate) fn extract_attrs(html: &Html, selector_str: &str, attr: &str) -> DonResult<Vec<String>> {
+    Ok(html
+        .select(&selector(selector_str)?)
+        .filter_map(|element| element.value().attr(attr))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Like `extract_texts`, but only the first one. Metadata is optional, so missing tags aren't
+/// errors.
+pub(crate) fn extract_text(html: &Html, selector_str: &str) -> DonResult<Option<String>> {
+    Ok(extract_texts(html, selector_str)?.into_iter().next())
+}
+
+/// Settings of the built-in downloaders, from the config.
+#[derive(Default)]
+pub(crate) struct Sites {
+    pub(crate) wallhaven_api_key: Option<String>,
+    pub(crate) flickr: Flickr,
+    pub(crate) imgur: Imgur,
+    pub(crate) unsplash: Unsplash,
+}
+
+pub(crate) struct Registry {
+    downloaders: Vec<Box<dyn Downloader>>,
+}
+
+impl Registry {
+    pub(crate) fn new() -> Self {
+        Registry {
+            downloaders: vec![],
+        }
+    }
+
+    pub(crate) fn register(&mut self, downloader: impl Downloader + 'static) -> &mut Self {
+        self.downloaders.push(Box::new(downloader));
+        self
+    }
+
+    /// Returns the first registered downloader handling this url.
+    pub(crate) fn find(&self, url: &Url) -> Option<&dyn Downloader> {
+        self.downloaders
+            .iter()
+            .find(|downloader| downloader.handles(url))
+            .map(|downloader| &**downloader)
+    }
+
+    /// Like `find`, but falls back to asking the server what the url points to, so that links to
+    /// images without an extension are downloaded too.
+    pub(crate) fn find_or_probe(
+        &self,
+        client: &dyn HttpClient,
+        url: &Url,
+    ) -> Option<&dyn Downloader> {
+        self.find(url).or_else(|| {
+            let content_type = client.content_type(url.as_str()).ok()??;
+            content_type
+                .starts_with("image/")
+                .then_some(&DirectImage as &dyn Downloader)
+        })
+    }
+
+    /// Registers the rules read from the config before the built-in downloaders, so that a rule
+    /// can take over a domain whose built-in scraper broke. Direct links to images come last, as
+    /// the sites above also link to their images.
+    pub(crate) fn with_rules(rules: &[ScrapingRule], sites: Sites) -> Self {
+        let mut registry = Registry::new();
+        rules.iter().for_each(|rule| {
+            registry.register(rule.clone());
+        });
+        registry
+            .register(sites.flickr)
+            .register(Wallhaven::new(sites.wallhaven_api_key))
+            .register(WallpaperFlare)
+            .register(Reddit)
+            .register(sites.imgur)
+            .register(sites.unsplash)
+            .register(DirectImage);
+        registry
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::with_rules(&[], Sites::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::test_helpers::FakeClient, *};
+
+    #[test]
+    fn test_registry_matches_subdomains() {
+        let registry = Registry::default();
+        for url in [
+            "https://www.flickr.com/photos/jdoe/52871234567",
+            "https://flickr.com/photos/jdoe/52871234567",
+            "https://wallhaven.cc/w/zy3l5o",
+            "https://whvn.cc/zy3l5o",
+            "https://www.wallpaperflare.com/mountain-lake-wallpaper-pxzyg",
+            "https://www.reddit.com/r/wallpaper/comments/1c2d3e4/lofoten_at_night_3840x2160/",
+            "https://redd.it/1c2d3e4",
+            "https://imgur.com/a/AbC12de",
+            "https://unsplash.com/photos/Xy1Z2abCdEf",
+            "https://example.com/wallpapers/forest.jpg",
+        ] {
+            assert!(registry.find(&Url::parse(url).unwrap()).is_some(), "{url}");
+        }
+        for url in [
+            "https://notflickr.com/photos/jdoe/52871234567",
+            "https://example.com/wallpapers/forest",
+        ] {
+            assert!(registry.find(&Url::parse(url).unwrap()).is_none(), "{url}");
+        }
+    }
+
+    #[test]
+    fn test_probe_content_type_of_unknown_urls() {
+        let registry = Registry::default();
+        let client = FakeClient::new(&[])
+            .with_content_type("https://example.com/random", "image/jpeg")
+            .with_content_type("https://example.com/page", "text/html; charset=utf-8");
+        let probe = |url: &str| registry.find_or_probe(&client, &Url::parse(url).unwrap());
+        assert!(probe("https://example.com/random")
+            .is_some_and(|downloader| downloader.domains().is_empty()));
+        assert!(probe("https://example.com/page").is_none());
+        // Unreachable
+        assert!(probe("https://example.com/missing").is_none());
+        // Known sites aren't probed
+        assert!(probe("https://wallhaven.cc/w/zy3l5o").is_some());
+    }
+
+    #[test]
+    fn test_rules_take_precedence_over_built_in_downloaders() {
+        let rule = ScrapingRule {
+            domain: "wallhaven.cc".to_string(),
+            rewrites: vec![],
+            steps: vec![],
+            metadata: Default::default(),
+        };
+        let registry = Registry::with_rules(&[rule], Sites::default());
+        let downloader = registry
+            .find(&Url::parse("https://wallhaven.cc/w/zy3l5o").unwrap())
+            .unwrap();
+        // Only the rule fails when it has no step
+        assert!(downloader
+            .scrape(&FakeClient::new(&[]), "https://wallhaven.cc/w/zy3l5o")
+            .is_err_and(|err| err.to_string().contains("doesn't have any step")));
+    }
+}
@@ -1,24 +1,602 @@
-use don_error::*;
+use super::{
+    extract_attr, extract_attrs, extract_text, extract_texts, Downloader, HttpClient, Metadata,
+    Scraped,
+};
 
-pub(crate) fn download(url: &str) -> DonResult<()> {
-    println!("Downloading from {url}");
+use {
+    don_error::*,
+    scraper::Html,
+    serde::{de::DeserializeOwned, Deserialize},
+    serde_json::Value,
+    url::Url,
+};
 
-    let source_code = reqwest::blocking::get(format!("{url}/sizes/o"))?.text()?;
-    let html = scraper::Html::parse_document(&source_code);
-    let selector_str = "div#allsizes-photo>img";
-    let err_ctx = DonErrorContext::new().with_ser("source code", &source_code);
+const API_URL: &str = "https://api.flickr.com/services/rest";
 
-    super::download_file(
-        html.select(
-            &scraper::Selector::parse(selector_str)
-                .map_err(|_| err_msg!("Failed to create the selector for '{selector_str}'"))?,
+/// Albums and photostreams are expanded into that many photos at most.
+const MAX_PHOTOS: usize = 500;
+
+/// Names of the licences of Flickr, by id.
+const LICENCES: [&str; 11] = [
+    "All Rights Reserved",
+    "CC BY-NC-SA 2.0",
+    "CC BY-NC 2.0",
+    "CC BY-NC-ND 2.0",
+    "CC BY 2.0",
+    "CC BY-SA 2.0",
+    "CC BY-ND 2.0",
+    "No known copyright restrictions",
+    "United States Government Work",
+    "CC0 1.0",
+    "Public Domain Mark 1.0",
+];
+
+/// Photos of Flickr, found through the API when the config has a key and through the pages
+/// otherwise. Albums, galleries and photostreams are expanded into their photos.
+///
+/// ```toml
+/// [flickr]
+/// api_key = "0123456789abcdef"
+/// licences = ["CC BY 2.0", "CC BY-SA 2.0", "CC0 1.0", "Public Domain Mark 1.0"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Flickr {
+    #[serde(default)]
+    api_key: Option<String>,
+    /// Photos under another licence are skipped, any licence is fine when empty
+    #[serde(default)]
+    licences: Vec<String>,
+    #[serde(skip, default = "default_api_url")]
+    api_url: String,
+}
+
+impl Default for Flickr {
+    fn default() -> Self {
+        Flickr {
+            api_key: None,
+            licences: vec![],
+            api_url: default_api_url(),
+        }
+    }
+}
+
+fn default_api_url() -> String {
+    API_URL.to_string()
+}
+
+/// What a bookmarked url points to.
+#[derive(Debug, PartialEq)]
+enum Link {
+    Photo { id: String },
+    Album { id: String },
+    Gallery,
+    Photostream,
+}
+
+impl Link {
+    /// From `/photos/<user>/...` urls.
+    fn parse(url: &Url) -> Option<Self> {
+        let segments = url
+            .path_segments()?
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        match segments.as_slice() {
+            ["photos", _] => Some(Link::Photostream),
+            ["photos", _, "albums" | "sets", id, ..] => Some(Link::Album { id: id.to_string() }),
+            ["photos", _, "galleries", _, ..] => Some(Link::Gallery),
+            ["photos", _, id, ..] if id.chars().all(|c| c.is_ascii_digit()) => {
+                Some(Link::Photo { id: id.to_string() })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PhotoList {
+    /// A number, or a number as a string for albums
+    page: Value,
+    pages: Value,
+    photo: Vec<ListedPhoto>,
+    /// Only for albums, whose photos have no owner
+    owner: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ListedPhoto {
+    id: String,
+    owner: Option<String>,
+    license: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Sizes {
+    size: Vec<Size>,
+}
+
+/// Sizes are listed from the smallest to the largest.
+#[derive(Deserialize)]
+struct Size {
+    source: String,
+    media: String,
+}
+
+#[derive(Deserialize)]
+struct PhotoInfo {
+    license: Value,
+    owner: Owner,
+    title: Content,
+    tags: Tags,
+}
+
+#[derive(Deserialize)]
+struct Owner {
+    username: String,
+    #[serde(default)]
+    realname: String,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    _content: String,
+}
+
+#[derive(Deserialize)]
+struct Tags {
+    tag: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    raw: String,
+}
+
+/// Flickr sends numbers either as numbers or as strings.
+fn number(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(number) => number.as_u64(),
+        Value::String(string) => string.parse().ok(),
+        _ => None,
+    }
+}
+
+fn licence_name(id: &Value) -> Option<String> {
+    number(id)
+        .and_then(|id| LICENCES.get(id as usize))
+        .map(|name| name.to_string())
+}
+
+impl Flickr {
+    /// Calls the API, which answers with the result under `key`.
+    fn call<T: DeserializeOwned>(
+        &self,
+        client: &dyn HttpClient,
+        api_key: &str,
+        method: &str,
+        params: &[(&str, &str)],
+        key: &str,
+    ) -> DonResult<T> {
+        let mut url = Url::parse(&self.api_url)?;
+        url.query_pairs_mut()
+            .append_pair("method", method)
+            .extend_pairs(params)
+            .append_pair("api_key", api_key)
+            .append_pair("format", "json")
+            .append_pair("nojsoncallback", "1");
+        let mut response: Value = serde_json::from_str(&client.get_text(url.as_str())?)?;
+        if response["stat"] != "ok" {
+            bail!(
+                "Flickr API {method} failed : {}",
+                response["message"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(serde_json::from_value(response[key].take())?)
+    }
+
+    fn allows(&self, licence: Option<&str>) -> bool {
+        self.licences.is_empty()
+            || licence.is_some_and(|licence| {
+                self.licences
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(licence))
+            })
+    }
+
+    /// Urls of the photos of an album, gallery or photostream, through the API. Photos whose
+    /// licence isn't allowed are left out already.
+    fn expand_with_api(
+        &self,
+        client: &dyn HttpClient,
+        api_key: &str,
+        url: &str,
+        link: &Link,
+    ) -> DonResult<Vec<String>> {
+        let (method, id_param, id, key) = match link {
+            Link::Photo { .. } => return Ok(vec![url.to_string()]),
+            Link::Album { id } => (
+                "flickr.photosets.getPhotos",
+                "photoset_id",
+                id.clone(),
+                "photoset",
+            ),
+            Link::Gallery => {
+                #[derive(Deserialize)]
+                struct Gallery {
+                    id: String,
+                }
+                let gallery: Gallery = self.call(
+                    client,
+                    api_key,
+                    "flickr.urls.lookupGallery",
+                    &[("url", url)],
+                    "gallery",
+                )?;
+                (
+                    "flickr.galleries.getPhotos",
+                    "gallery_id",
+                    gallery.id,
+                    "photos",
+                )
+            }
+            Link::Photostream => {
+                #[derive(Deserialize)]
+                struct User {
+                    id: String,
+                }
+                let user: User = self.call(
+                    client,
+                    api_key,
+                    "flickr.urls.lookupUser",
+                    &[("url", url)],
+                    "user",
+                )?;
+                (
+                    "flickr.people.getPublicPhotos",
+                    "user_id",
+                    user.id,
+                    "photos",
+                )
+            }
+        };
+
+        let mut urls = vec![];
+        let mut page = 1;
+        loop {
+            let list: PhotoList = self.call(
+                client,
+                api_key,
+                method,
+                &[
+                    (id_param, &id),
+                    ("extras", "license"),
+                    ("per_page", "500"),
+                    ("page", &page.to_string()),
+                ],
+                key,
+            )?;
+            for photo in &list.photo {
+                let licence = photo.license.as_ref().and_then(licence_name);
+                let Some(owner) = photo.owner.as_ref().or(list.owner.as_ref()) else {
+                    continue;
+                };
+                if self.allows(licence.as_deref()) {
+                    urls.push(format!(
+                        "https://www.flickr.com/photos/{owner}/{}",
+                        photo.id
+                    ));
+                }
+            }
+            let last_page = number(&list.pages).unwrap_or_default();
+            if number(&list.page).unwrap_or(last_page) >= last_page || urls.len() >= MAX_PHOTOS {
+                break;
+            }
+            page += 1;
+        }
+        urls.truncate(MAX_PHOTOS);
+        Ok(urls)
+    }
+
+    /// Urls of the photos linked from the first page of an album, gallery or photostream.
+    fn expand_from_page(&self, client: &dyn HttpClient, url: &str) -> DonResult<Vec<String>> {
+        let page_url = Url::parse(url)?;
+        let html = Html::parse_document(&self.fetch_page(client, url)?);
+        let mut urls = vec![];
+        for href in extract_attrs(&html, "a.overlay", "href")? {
+            let Ok(photo_url) = page_url.join(&href) else {
+                continue;
+            };
+            // Without the "/in/album-<id>" suffix
+            let Some(Link::Photo { .. }) = Link::parse(&photo_url) else {
+                continue;
+            };
+            let segments = photo_url
+                .path_segments()
+                .map(|segments| segments.take(3).collect::<Vec<_>>().join("/"))
+                .unwrap_or_default();
+            let photo_url = format!("https://www.flickr.com/{segments}");
+            if !urls.contains(&photo_url) {
+                urls.push(photo_url);
+            }
+        }
+        if urls.is_empty() {
+            bail!("No photo found on {url}");
+        }
+        urls.truncate(MAX_PHOTOS);
+        Ok(urls)
+    }
+
+    fn scrape_with_api(
+        &self,
+        client: &dyn HttpClient,
+        api_key: &str,
+        id: &str,
+    ) -> DonResult<Scraped> {
+        let sizes: Sizes = self.call(
+            client,
+            api_key,
+            "flickr.photos.getSizes",
+            &[("photo_id", id)],
+            "sizes",
+        )?;
+        let largest = sizes
+            .size
+            .into_iter()
+            .rfind(|size| size.media == "photo")
+            .ok_or_don_err(format!("Photo {id} has no size"))?;
+        let info: PhotoInfo = self.call(
+            client,
+            api_key,
+            "flickr.photos.getInfo",
+            &[("photo_id", id)],
+            "photo",
+        )?;
+        let author = match info.owner.realname.is_empty() {
+            true => info.owner.username,
+            false => info.owner.realname,
+        };
+        let title = info.title._content;
+        Ok(Scraped {
+            image_url: largest.source,
+            metadata: Metadata {
+                title: (!title.is_empty()).then_some(title),
+                author: Some(author),
+                licence: licence_name(&info.license),
+                tags: info.tags.tag.into_iter().map(|tag| tag.raw).collect(),
+            },
+        })
+    }
+
+    fn scrape_page(&self, client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped> {
+        let page = self.fetch_page(client, page_url)?;
+        let image_url = extract_attr(&page, "div#allsizes-photo>img", "src")?;
+        let html = Html::parse_document(&page);
+        Ok(Scraped {
+            image_url,
+            metadata: Metadata {
+                // "All sizes | <title> | Flickr - Photo Sharing!"
+                title: extract_text(&html, "title")?.and_then(|title| {
+                    let parts = title.split(" | ").collect::<Vec<_>>();
+                    match parts.as_slice() {
+                        [_, title @ .., _] if !title.is_empty() => Some(title.join(" | ")),
+                        _ => None,
+                    }
+                }),
+                author: extract_text(&html, "a.owner-name")?,
+                licence: extract_text(&html, "a.photo-license-url")?,
+                tags: extract_texts(&html, "ul.tags-list a.tag")?,
+            },
+        })
+    }
+}
+
+impl Downloader for Flickr {
+    fn domains(&self) -> Vec<&str> {
+        vec!["flickr.com"]
+    }
+
+    fn expand(&self, client: &dyn HttpClient, url: &str) -> DonResult<Vec<String>> {
+        let link = Link::parse(&Url::parse(url)?);
+        match (&self.api_key, link) {
+            (_, None | Some(Link::Photo { .. })) => Ok(vec![url.to_string()]),
+            (Some(api_key), Some(link)) => self.expand_with_api(client, api_key, url, &link),
+            (None, Some(_)) => self.expand_from_page(client, url),
+        }
+    }
+
+    /// The API only needs the url of the photo, the sizes page of the original is scraped
+    /// otherwise.
+    fn page_url(&self, url: &str) -> String {
+        match self.api_key {
+            Some(_) => url.to_string(),
+            None => format!("{}/sizes/o", url.trim_end_matches('/')),
+        }
+    }
+
+    fn scrape(&self, client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped> {
+        match (&self.api_key, Link::parse(&Url::parse(page_url)?)) {
+            (Some(api_key), Some(Link::Photo { id })) => self.scrape_with_api(client, api_key, &id),
+            _ => self.scrape_page(client, page_url),
+        }
+    }
+
+    fn rejects(&self, metadata: &Metadata) -> Option<String> {
+        match self.allows(metadata.licence.as_deref()) {
+            true => None,
+            false => Some(format!(
+                "Licence {} isn't allowed",
+                metadata.licence.as_deref().unwrap_or("unknown")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::test_helpers::FakeClient, *};
+
+    const PHOTO_URL: &str = "https://www.flickr.com/photos/jdoe/52871234567/";
+
+    fn with_api(licences: &[&str]) -> Flickr {
+        Flickr {
+            api_key: Some("s3cr3t".to_string()),
+            licences: licences.iter().map(|licence| licence.to_string()).collect(),
+            api_url: "https://api.flickr.test/rest".to_string(),
+        }
+    }
+
+    fn api_url(method: &str, params: &str) -> String {
+        format!(
+            "https://api.flickr.test/rest?method={method}&{params}api_key=s3cr3t&format=json&nojsoncallback=1"
         )
-        .next()
-        .ok_or_don_err("Couldn't find any tag 'div' with id 'allsizes-photo'")
-        .err_ctx(&err_ctx)?
-        .value()
-        .attr("src")
-        .ok_or_don_err("The tag was found but doesn't contain an attribute 'src'")
-        .err_ctx(&err_ctx)?,
-    )
+    }
+
+    #[test]
+    fn test_links() {
+        let parse = |url: &str| Link::parse(&Url::parse(url).unwrap());
+        assert_eq!(
+            parse(PHOTO_URL),
+            Some(Link::Photo {
+                id: "52871234567".to_string()
+            })
+        );
+        assert_eq!(
+            parse("https://www.flickr.com/photos/jdoe/52871234567/in/album-72177720300000000/"),
+            Some(Link::Photo {
+                id: "52871234567".to_string()
+            })
+        );
+        assert_eq!(
+            parse("https://www.flickr.com/photos/jdoe/sets/72177720300000000"),
+            Some(Link::Album {
+                id: "72177720300000000".to_string()
+            })
+        );
+        assert_eq!(
+            parse("https://www.flickr.com/photos/jdoe/galleries/72157721111111111/"),
+            Some(Link::Gallery)
+        );
+        assert_eq!(
+            parse("https://www.flickr.com/photos/jdoe/"),
+            Some(Link::Photostream)
+        );
+        assert_eq!(parse("https://www.flickr.com/explore"), None);
+    }
+
+    #[test]
+    fn test_page_url_points_to_original_size() {
+        assert_eq!(
+            Flickr::default().page_url(PHOTO_URL),
+            "https://www.flickr.com/photos/jdoe/52871234567/sizes/o"
+        );
+        assert_eq!(with_api(&[]).page_url(PHOTO_URL), PHOTO_URL);
+    }
+
+    #[test]
+    fn test_scrape() {
+        let flickr = Flickr::default();
+        let page_url = flickr.page_url(PHOTO_URL);
+        let client = FakeClient::new(&[(&page_url, include_str!("fixtures/flickr_sizes.html"))]);
+        assert_eq!(
+            flickr.scrape(&client, &page_url).unwrap(),
+            Scraped {
+                image_url: "https://live.staticflickr.com/65535/52871234567_0a1b2c3d4e_o.jpg"
+                    .to_string(),
+                metadata: Metadata {
+                    title: Some("Misty mountains at dawn".to_string()),
+                    author: Some("John Doe".to_string()),
+                    licence: Some("CC BY-SA 2.0".to_string()),
+                    tags: vec!["mountains".to_string(), "fog".to_string()],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_scrape_largest_size_with_api() {
+        let client = FakeClient::new(&[
+            (
+                &api_url("flickr.photos.getSizes", "photo_id=52871234567&"),
+                include_str!("fixtures/flickr_api_sizes.json"),
+            ),
+            (
+                &api_url("flickr.photos.getInfo", "photo_id=52871234567&"),
+                include_str!("fixtures/flickr_api_info.json"),
+            ),
+        ]);
+        let flickr = with_api(&["CC BY 2.0"]);
+        let scraped = flickr.scrape(&client, PHOTO_URL).unwrap();
+        assert_eq!(
+            scraped,
+            Scraped {
+                image_url: "https://live.staticflickr.com/65535/52871234567_0a1b2c3d4e_k.jpg"
+                    .to_string(),
+                metadata: Metadata {
+                    title: Some("Misty mountains at dawn".to_string()),
+                    author: Some("John Doe".to_string()),
+                    licence: Some("CC BY-SA 2.0".to_string()),
+                    tags: vec!["Mountains".to_string(), "fog".to_string()],
+                },
+            }
+        );
+        assert_eq!(
+            flickr.rejects(&scraped.metadata).as_deref(),
+            Some("Licence CC BY-SA 2.0 isn't allowed")
+        );
+        assert_eq!(with_api(&[]).rejects(&scraped.metadata), None);
+    }
+
+    #[test]
+    fn test_expand_album_with_api() {
+        let album_url = "https://www.flickr.com/photos/jdoe/albums/72177720300000000";
+        let page = |number| {
+            api_url(
+                "flickr.photosets.getPhotos",
+                &format!(
+                    "photoset_id=72177720300000000&extras=license&per_page=500&page={number}&"
+                ),
+            )
+        };
+        let client = FakeClient::new(&[
+            (&page(1), include_str!("fixtures/flickr_api_album_1.json")),
+            (&page(2), include_str!("fixtures/flickr_api_album_2.json")),
+        ]);
+        assert_eq!(
+            with_api(&[]).expand(&client, album_url).unwrap(),
+            [
+                "https://www.flickr.com/photos/12345678@N00/52871234567",
+                "https://www.flickr.com/photos/12345678@N00/52871234568",
+                "https://www.flickr.com/photos/12345678@N00/52871234569",
+            ]
+        );
+        // The second photo is "All Rights Reserved", the third has no licence
+        assert_eq!(
+            with_api(&["cc by 2.0", "CC BY-SA 2.0"])
+                .expand(&client, album_url)
+                .unwrap(),
+            ["https://www.flickr.com/photos/12345678@N00/52871234567"]
+        );
+    }
+
+    #[test]
+    fn test_expand_photostream_from_page() {
+        let photostream_url = "https://www.flickr.com/photos/jdoe/";
+        let client = FakeClient::new(&[(
+            photostream_url,
+            include_str!("fixtures/flickr_photostream.html"),
+        )]);
+        assert_eq!(
+            Flickr::default().expand(&client, photostream_url).unwrap(),
+            [
+                "https://www.flickr.com/photos/jdoe/52871234567",
+                "https://www.flickr.com/photos/jdoe/52871234568",
+            ]
+        );
+        // A single photo isn't fetched
+        assert_eq!(
+            Flickr::default()
+                .expand(&FakeClient::new(&[]), PHOTO_URL)
+                .unwrap(),
+            [PHOTO_URL]
+        );
+    }
 }
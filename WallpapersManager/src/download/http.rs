@@ -0,0 +1,286 @@
+use {
+    don_error::*,
+    reqwest::{
+        header::{CONTENT_TYPE, RANGE, RETRY_AFTER},
+        StatusCode,
+    },
+    std::{
+        fs::File,
+        io::{Seek, SeekFrom},
+        thread::sleep,
+        time::Duration,
+    },
+};
+
+/// How many times a request is sent again when the server answers that too many were sent.
+const MAX_RETRIES: u32 = 5;
+
+/// Everything the downloaders need from the network, so that they can be run against recorded
+/// pages in tests.
+pub(crate) trait HttpClient: Sync {
+    fn get_text(&self, url: &str) -> DonResult<String> {
+        self.get_text_with_headers(url, &[])
+    }
+
+    /// Headers are meant for what shouldn't end up in urls, like API keys.
+    fn get_text_with_headers(&self, url: &str, headers: &[(&str, &str)]) -> DonResult<String>;
+
+    /// Content type the server announces for `url`, without downloading it.
+    fn content_type(&self, url: &str) -> DonResult<Option<String>>;
+
+    /// Writes the body to `file`. If `file` already contains the beginning of the body, only the
+    /// missing bytes are requested.
+    fn download_to(&self, url: &str, file: &mut File) -> DonResult<Downloaded>;
+}
+
+pub(crate) struct Downloaded {
+    pub(crate) content_type: Option<String>,
+}
+
+pub(crate) struct ReqwestClient {
+    client: reqwest::blocking::Client,
+}
+
+impl ReqwestClient {
+    pub(crate) fn new() -> Self {
+        ReqwestClient {
+            // Some sites, like Reddit, throttle clients without a user agent
+            client: reqwest::blocking::Client::builder()
+                .user_agent(concat!("wallpapers-manager/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .expect("The TLS backend can be initialized"),
+        }
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    /// Rate limited requests are sent again after the delay asked by the server, or an
+    /// exponential backoff.
+    fn get_text_with_headers(&self, url: &str, headers: &[(&str, &str)]) -> DonResult<String> {
+        let mut retries = 0;
+        loop {
+            let mut request = self.client.get(url);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            let response = request.send()?;
+            if response.status() == StatusCode::TOO_MANY_REQUESTS && retries < MAX_RETRIES {
+                retries += 1;
+                sleep(retry_after(&response).unwrap_or(Duration::from_secs(1 << retries)));
+                continue;
+            }
+            return Ok(response.error_for_status()?.text()?);
+        }
+    }
+
+    fn content_type(&self, url: &str) -> DonResult<Option<String>> {
+        let response = self.client.head(url).send()?.error_for_status()?;
+        Ok(content_type(&response))
+    }
+
+    fn download_to(&self, url: &str, file: &mut File) -> DonResult<Downloaded> {
+        let resume_from = file.metadata()?.len();
+        let mut request = self.client.get(url);
+        if resume_from > 0 {
+            request = request.header(RANGE, format!("bytes={resume_from}-"));
+        }
+        let mut response = request.send()?;
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                file.seek(SeekFrom::End(0))?;
+            }
+            // The previous run was interrupted after writing the whole body
+            StatusCode::RANGE_NOT_SATISFIABLE if resume_from > 0 => {
+                return Ok(Downloaded { content_type: None })
+            }
+            _ => {
+                // The server ignored the range, so everything is downloaded again
+                response = response.error_for_status()?;
+                file.set_len(0)?;
+                file.seek(SeekFrom::Start(0))?;
+            }
+        }
+        let content_type = content_type(&response);
+        response.copy_to(file)?;
+        Ok(Downloaded { content_type })
+    }
+}
+
+fn retry_after(response: &reqwest::blocking::Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    Some(Duration::from_secs(seconds.parse().ok()?))
+}
+
+fn content_type(response: &reqwest::blocking::Response) -> Option<String> {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use super::*;
+
+    use std::{collections::HashMap, io::Write};
+
+    /// Answers with pages recorded beforehand, keyed by their url.
+    pub(crate) struct FakeClient {
+        pages: HashMap<String, String>,
+        content_types: HashMap<String, String>,
+    }
+
+    impl FakeClient {
+        pub(crate) fn new(pages: &[(&str, &str)]) -> Self {
+            FakeClient {
+                pages: pages
+                    .iter()
+                    .map(|(url, page)| (url.to_string(), page.to_string()))
+                    .collect(),
+                content_types: HashMap::new(),
+            }
+        }
+
+        pub(crate) fn with_content_type(mut self, url: &str, content_type: &str) -> Self {
+            self.content_types
+                .insert(url.to_string(), content_type.to_string());
+            self
+        }
+    }
+
+    impl HttpClient for FakeClient {
+        fn get_text_with_headers(&self, url: &str, _: &[(&str, &str)]) -> DonResult<String> {
+            self.pages
+                .get(url)
+                .cloned()
+                .ok_or_don_err(format!("No page recorded for {url}"))
+        }
+
+        fn content_type(&self, url: &str) -> DonResult<Option<String>> {
+            Ok(self.content_types.get(url).cloned())
+        }
+
+        fn download_to(&self, url: &str, file: &mut File) -> DonResult<Downloaded> {
+            let page = self.get_text(url)?;
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(page.as_bytes())?;
+            Ok(Downloaded { content_type: None })
+        }
+    }
+
+    /// Serves `pages` (path, body) on a random local port and returns the base url of the server.
+    /// Range requests of the form `bytes=<start>-` are honoured. The server lives until the end of
+    /// the test process.
+    pub(crate) fn serve(pages: Vec<(&'static str, String)>) -> String {
+        let server = tiny_http::Server::http("127.0.0.1:0").expect("Can't start stub server");
+        let base_url = format!("http://{}", server.server_addr());
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let range_start = request
+                    .headers()
+                    .iter()
+                    .find(|header| header.field.equiv("Range"))
+                    .and_then(|header| {
+                        header
+                            .value
+                            .as_str()
+                            .strip_prefix("bytes=")?
+                            .strip_suffix('-')?
+                            .parse::<usize>()
+                            .ok()
+                    });
+                let response = match pages.iter().find(|(path, _)| *path == request.url()) {
+                    Some((_, body)) => match range_start {
+                        Some(start) if start >= body.len() => {
+                            tiny_http::Response::from_string("").with_status_code(416)
+                        }
+                        Some(start) => {
+                            tiny_http::Response::from_string(&body[start..]).with_status_code(206)
+                        }
+                        None => tiny_http::Response::from_string(body.clone()),
+                    },
+                    None => tiny_http::Response::from_string("Not found").with_status_code(404),
+                };
+                let _ = request.respond(response);
+            }
+        });
+        base_url
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{test_helpers::serve, *};
+
+    use std::{
+        fs::{read_to_string, remove_file, OpenOptions},
+        io::Write,
+    };
+
+    fn part_file(name: &str, content: &str) -> (std::path::PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("{}_{name}.part", std::process::id()));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn test_rate_limited_request_is_sent_again_with_its_headers() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/search", server.server_addr());
+        std::thread::spawn(move || {
+            for (index, request) in server.incoming_requests().enumerate() {
+                let api_key = request
+                    .headers()
+                    .iter()
+                    .find(|header| header.field.equiv("X-API-Key"))
+                    .map(|header| header.value.to_string());
+                let response = match (index, api_key.as_deref()) {
+                    (0, _) => tiny_http::Response::from_string("Slow down")
+                        .with_status_code(429)
+                        .with_header("Retry-After: 0".parse::<tiny_http::Header>().unwrap()),
+                    (_, Some("s3cr3t")) => tiny_http::Response::from_string("results"),
+                    _ => tiny_http::Response::from_string("Unauthorized").with_status_code(401),
+                };
+                let _ = request.respond(response);
+            }
+        });
+        assert_eq!(
+            ReqwestClient::new()
+                .get_text_with_headers(&url, &[("X-API-Key", "s3cr3t")])
+                .unwrap(),
+            "results"
+        );
+    }
+
+    #[test]
+    fn test_download_is_resumed_with_range_request() {
+        let base_url = serve(vec![("/wallpaper.jpg", "0123456789".to_string())]);
+        // The first bytes differ from the served ones to check they weren't downloaded again
+        let (path, mut file) = part_file("resumed", "ABCD");
+        ReqwestClient::new()
+            .download_to(&format!("{base_url}/wallpaper.jpg"), &mut file)
+            .unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "ABCD456789");
+        remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_download_of_complete_part_file() {
+        let base_url = serve(vec![("/wallpaper.jpg", "0123456789".to_string())]);
+        let (path, mut file) = part_file("complete", "0123456789");
+        ReqwestClient::new()
+            .download_to(&format!("{base_url}/wallpaper.jpg"), &mut file)
+            .unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "0123456789");
+        remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use super::{direct::is_image_url, Downloader, HttpClient, Metadata, Scraped};
+
+use {don_error::*, serde::Deserialize, url::Url};
+
+const API_URL: &str = "https://api.imgur.com/3";
+
+/// Images and albums of Imgur. Albums are expanded into their images through the API, which
+/// needs the client id of an application registered on Imgur.
+///
+/// ```toml
+/// [imgur]
+/// client_id = "0123456789abcde"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct Imgur {
+    #[serde(default)]
+    client_id: Option<String>,
+}
+
+/// What a bookmarked url points to.
+#[derive(Debug, PartialEq)]
+enum Link {
+    /// Ex: "https://i.imgur.com/Fg7Hi8j.jpg"
+    Image,
+    /// Ex: "https://imgur.com/Fg7Hi8j"
+    Page { id: String },
+    /// Ex: "https://imgur.com/a/AbC12de", "https://imgur.com/gallery/foggy-forests-AbC12de"
+    Album { id: String },
+}
+
+impl Link {
+    fn parse(url: &Url) -> Option<Self> {
+        if is_image_url(url) {
+            return Some(Link::Image);
+        }
+        let segments = url
+            .path_segments()?
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        match segments[..] {
+            ["a" | "gallery", slug] => Some(Link::Album {
+                // The title of the album comes first in recent urls
+                id: slug.rsplit('-').next()?.to_string(),
+            }),
+            [id] => Some(Link::Page { id: id.to_string() }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct Album {
+    title: Option<String>,
+    /// Name of the uploader
+    account_url: Option<String>,
+    images: Vec<Image>,
+}
+
+#[derive(Deserialize)]
+struct Image {
+    id: String,
+    title: Option<String>,
+    link: String,
+}
+
+impl Imgur {
+    fn album(&self, client: &dyn HttpClient, id: &str) -> DonResult<Album> {
+        let Some(client_id) = &self.client_id else {
+            bail!("Imgur albums can only be downloaded with `imgur.client_id` set in the config");
+        };
+        let url = format!("{API_URL}/album/{id}");
+        let authorization = format!("Client-ID {client_id}");
+        let text = client.get_text_with_headers(&url, &[("Authorization", &authorization)])?;
+        let response: Response<Album> = serde_json::from_str(&text).err_ctx_val("album", id)?;
+        Ok(response.data)
+    }
+}
+
+impl Downloader for Imgur {
+    fn domains(&self) -> Vec<&str> {
+        vec!["imgur.com"]
+    }
+
+    /// Albums are expanded into their images, the id of each one as the fragment of the url.
+    fn expand(&self, client: &dyn HttpClient, url: &str) -> DonResult<Vec<String>> {
+        let mut parsed = Url::parse(url)?;
+        parsed.set_fragment(None);
+        Ok(match Link::parse(&parsed) {
+            Some(Link::Album { id }) => self
+                .album(client, &id)?
+                .images
+                .into_iter()
+                .map(|image| format!("{parsed}#{}", image.id))
+                .collect(),
+            _ => vec![url.to_string()],
+        })
+    }
+
+    fn scrape(&self, client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped> {
+        let url = Url::parse(page_url)?;
+        match Link::parse(&url).ok_or_don_err(format!("Not an Imgur image : {page_url}"))? {
+            Link::Image => Ok(Scraped {
+                image_url: page_url.to_string(),
+                metadata: Metadata::default(),
+            }),
+            // Imgur serves the image whatever the extension
+            Link::Page { id } => Ok(Scraped {
+                image_url: format!("https://i.imgur.com/{id}.jpg"),
+                metadata: Metadata::default(),
+            }),
+            Link::Album { id } => {
+                let album = self.album(client, &id)?;
+                let image = match url.fragment() {
+                    Some(image_id) => album
+                        .images
+                        .into_iter()
+                        .find(|image| image.id == image_id)
+                        .ok_or_don_err(format!("No image {image_id} in the album {id}"))?,
+                    None => album
+                        .images
+                        .into_iter()
+                        .next()
+                        .ok_or_don_err(format!("The album {id} is empty"))?,
+                };
+                Ok(Scraped {
+                    image_url: image.link,
+                    metadata: Metadata {
+                        title: image.title.or(album.title),
+                        author: album.account_url,
+                        licence: None,
+                        tags: vec![],
+                    },
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::test_helpers::FakeClient, *};
+
+    const ALBUM_URL: &str = "https://imgur.com/gallery/foggy-forests-AbC12de";
+
+    fn imgur() -> Imgur {
+        Imgur {
+            client_id: Some("c1i2d3".to_string()),
+        }
+    }
+
+    fn client() -> FakeClient {
+        FakeClient::new(&[(
+            "https://api.imgur.com/3/album/AbC12de",
+            include_str!("fixtures/imgur_album.json"),
+        )])
+    }
+
+    #[test]
+    fn test_parse_link() {
+        let parse = |url: &str| Link::parse(&Url::parse(url).unwrap());
+        assert_eq!(parse("https://i.imgur.com/Fg7Hi8j.jpg"), Some(Link::Image));
+        assert_eq!(
+            parse("https://imgur.com/Fg7Hi8j"),
+            Some(Link::Page {
+                id: "Fg7Hi8j".to_string()
+            })
+        );
+        for url in [ALBUM_URL, "https://imgur.com/a/AbC12de/"] {
+            assert_eq!(
+                parse(url),
+                Some(Link::Album {
+                    id: "AbC12de".to_string()
+                }),
+                "{url}"
+            );
+        }
+        assert_eq!(parse("https://imgur.com/user/jdoe/posts"), None);
+    }
+
+    #[test]
+    fn test_album() {
+        let client = client();
+        let images = imgur().expand(&client, ALBUM_URL).unwrap();
+        assert_eq!(
+            images,
+            [
+                format!("{ALBUM_URL}#Fg7Hi8j"),
+                format!("{ALBUM_URL}#Kl9Mn0p")
+            ]
+        );
+        assert_eq!(
+            imgur().scrape(&client, &images[0]).unwrap(),
+            Scraped {
+                image_url: "https://i.imgur.com/Fg7Hi8j.jpg".to_string(),
+                metadata: Metadata {
+                    title: Some("Foggy forests".to_string()),
+                    author: Some("jdoe".to_string()),
+                    ..Default::default()
+                },
+            }
+        );
+        assert_eq!(
+            imgur().scrape(&client, &images[1]).unwrap().metadata.title,
+            Some("Morning".to_string())
+        );
+    }
+
+    #[test]
+    fn test_album_needs_client_id() {
+        assert!(Imgur::default()
+            .expand(&client(), ALBUM_URL)
+            .is_err_and(|err| err.to_string().contains("imgur.client_id")));
+        // Single images don't
+        assert_eq!(
+            Imgur::default()
+                .scrape(&client(), "https://imgur.com/Fg7Hi8j")
+                .unwrap()
+                .image_url,
+            "https://i.imgur.com/Fg7Hi8j.jpg"
+        );
+    }
+}
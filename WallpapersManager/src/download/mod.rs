@@ -1,70 +1,360 @@
+mod direct;
+mod downloader;
 mod flickr;
+mod http;
+mod imgur;
+mod naming;
+mod pipeline;
+mod reddit;
+mod rules;
+mod unsplash;
+mod validate;
 mod wallhaven;
 mod wallpaper_flare;
 
-use crate::{wallpapers::sort, CONFIG};
+pub use wallhaven::Search as WallhavenSearch;
+
+pub(crate) use {
+    downloader::{
+        extract_attr, extract_attrs, extract_text, extract_texts, Downloader, Metadata, Registry,
+        Scraped, Sites,
+    },
+    flickr::Flickr,
+    http::{HttpClient, ReqwestClient},
+    imgur::Imgur,
+    naming::NamingStrategy,
+    rules::ScrapingRule,
+    unsplash::Unsplash,
+};
+
+#[cfg(test)]
+pub(crate) use http::test_helpers;
+
+use crate::{
+    catalogue::{Catalogue, NewDownload},
+    wallpapers::{
+        content_hashes::{hash, ContentHashes},
+        sort::{self, available_path},
+    },
+    CONFIG,
+};
 
 use {
     don_error::*,
-    firefox_sync_sdk::{Bookmark, Client as FirefoxSyncClient, CreateFolderInput},
-    std::fs::File,
+    naming::NameSources,
+    pipeline::{run_parallel, Report, FAILED_FOLDER, UNSUPPORTED_DOMAINS_FOLDER},
+    std::{
+        collections::HashSet,
+        fs::{read, remove_file, rename, OpenOptions},
+        path::{Path, PathBuf},
+        sync::Mutex,
+    },
     url::Url,
+    validate::{base_name, validate},
+    wallhaven::{Search, Wallhaven},
 };
 
+/// Where and how the downloaded wallpapers are saved.
+struct Library {
+    dir: PathBuf,
+    naming_strategy: NamingStrategy,
+    content_hashes: Mutex<ContentHashes>,
+}
+
+enum Saved {
+    New(NewFile),
+    /// The same image was already in the library, at this path
+    Duplicate(PathBuf),
+    /// Not downloaded, for this reason
+    Skipped(String),
+}
+
+struct NewFile {
+    path: PathBuf,
+    image_url: String,
+    metadata: Metadata,
+    content_hash: String,
+    width: u32,
+    height: u32,
+}
+
+impl Library {
+    fn open() -> DonResult<Self> {
+        Ok(Library {
+            dir: PathBuf::from(&CONFIG.wallpapers_dir),
+            naming_strategy: CONFIG.naming_strategy,
+            content_hashes: Mutex::new(ContentHashes::load(Path::new(&CONFIG.wallpapers_dir))?),
+        })
+    }
+
+    fn save(self) -> DonResult<()> {
+        self.content_hashes
+            .into_inner()
+            .expect("No thread panics while holding the lock")
+            .save()
+    }
+}
+
+/// The site a wallpaper comes from, which is the domain of its url for the downloaders of images
+/// of any site.
+fn site(downloader: &dyn Downloader, url: &str) -> String {
+    match downloader.domains().first() {
+        Some(domain) => domain.to_string(),
+        None => Url::parse(url)
+            .ok()
+            .and_then(|url| Some(url.domain()?.trim_start_matches("www.").to_string()))
+            .unwrap_or_else(|| "unknown".to_string()),
+    }
+}
+
+/// Records what was downloaded from `source_url` in the catalogue and the report.
+fn record(
+    catalogue: &Catalogue,
+    report: &mut Report,
+    saved: Saved,
+    source_url: &str,
+    downloader: &dyn Downloader,
+    bookmark_title: Option<&str>,
+) {
+    match saved {
+        Saved::New(file) => {
+            try_or_report(|| {
+                catalogue.record_download(&NewDownload {
+                    path: &file.path,
+                    hash: &file.content_hash,
+                    source_url,
+                    image_url: &file.image_url,
+                    site: &site(downloader, source_url),
+                    bookmark_title,
+                    metadata: &file.metadata,
+                    width: file.width,
+                    height: file.height,
+                })
+            });
+            report.nb_downloaded += 1
+        }
+        Saved::Duplicate(existing) => report.duplicates.push((source_url.to_string(), existing)),
+        Saved::Skipped(reason) => report.skipped.push((source_url.to_string(), reason)),
+    }
+}
+
 pub fn perform() -> DonResult<()> {
     let client = &CONFIG.firefox_sync_client;
     let to_download = client.get_folder("toolbar/Wallpaper/Download")?;
-    // TODO : Folder::get_or_create_subfolder
-    let unsupported_domains_folder = match to_download
-        .sub_folders()
-        .find(|folder| folder.title == "Unsupported domains")
-    {
-        Some(folder) => folder,
-        None => &client.create_folder(&CreateFolderInput {
-            title: "Unsupported domains",
-            parent_id: &to_download.id,
-        })?,
+    let http_client = ReqwestClient::new();
+    let registry = Registry::with_rules(
+        &CONFIG.scraping_rules,
+        Sites {
+            wallhaven_api_key: CONFIG.wallhaven_api_key.clone(),
+            flickr: CONFIG.flickr.clone(),
+            imgur: CONFIG.imgur.clone(),
+            unsplash: CONFIG.unsplash.clone(),
+        },
+    );
+    let library = Library::open()?;
+    let catalogue = Catalogue::open()?;
+    let mut report = Report {
+        from_bookmarks: true,
+        ..Default::default()
     };
-    fn download_and_delete_bookmark(
-        client: &FirefoxSyncClient,
-        download_fn: impl Fn(&str) -> DonResult<()>,
-        bookmark: &Bookmark,
-    ) -> DonResult<()> {
-        download_fn(&bookmark.url)?;
-        // TODO : Bookmark::delete
-        client.delete_bookmark(&bookmark.id)
-    }
+
+    let mut jobs = vec![];
+    let mut unsupported = vec![];
     for bookmark in to_download.bookmarks() {
+        match Url::parse(&bookmark.url)
+            .ok()
+            .and_then(|url| registry.find_or_probe(&http_client, &url))
         {
-            match Url::parse(&bookmark.url)?.domain() {
-                Some("flickr") => download_and_delete_bookmark(client, flickr::download, bookmark)?,
-                Some("wallhaven") => {
-                    download_and_delete_bookmark(client, wallhaven::download, bookmark)?
-                }
-                Some("wallpaperflare") => {
-                    download_and_delete_bookmark(client, wallpaper_flare::download, bookmark)?
-                }
-                // TODO Bookmark::move
-                _ => client.move_bookmark(bookmark, unsupported_domains_folder)?,
+            Some(downloader) => jobs.push((bookmark, downloader)),
+            None => unsupported.push(bookmark),
+        }
+    }
+    if !unsupported.is_empty() {
+        let unsupported_domains_folder =
+            client.get_or_create_sub_folder(&to_download, UNSUPPORTED_DOMAINS_FOLDER)?;
+        for bookmark in unsupported {
+            // TODO Bookmark::move
+            client.move_bookmark(bookmark, &unsupported_domains_folder)?;
+            report.unsupported.push(bookmark.url.clone());
+        }
+    }
+
+    // Albums and photostreams stand for several wallpapers, each downloaded on its own. A bookmark
+    // is only deleted once all of them are, and kept when there are none.
+    let mut failed = HashSet::new();
+    let mut wallpapers = vec![];
+    for (index, (bookmark, downloader)) in jobs.iter().enumerate() {
+        match downloader.expand(&http_client, &bookmark.url) {
+            Ok(urls) if urls.is_empty() => {
+                failed.insert(index);
+                report
+                    .failed
+                    .push((bookmark.url.clone(), err_msg!("No images found")));
+            }
+            Ok(urls) => wallpapers.extend(urls.into_iter().map(|url| (index, url))),
+            Err(err) => {
+                failed.insert(index);
+                report.failed.push((bookmark.url.clone(), err));
+            }
+        }
+    }
+    let results = run_parallel(
+        &wallpapers,
+        CONFIG.download_workers,
+        |(_, url)| url.clone(),
+        |(index, url)| download(&http_client, &library, jobs[*index].1, url),
+    );
+    for ((index, url), result) in wallpapers.iter().zip(results) {
+        let (bookmark, downloader) = jobs[*index];
+        match result {
+            Ok(saved) => record(
+                &catalogue,
+                &mut report,
+                saved,
+                url,
+                downloader,
+                Some(&bookmark.title),
+            ),
+            Err(err) => {
+                failed.insert(*index);
+                report.failed.push((url.clone(), err));
             }
         }
     }
+    let failed_folder = match failed.is_empty() {
+        false => Some(client.get_or_create_sub_folder(&to_download, FAILED_FOLDER)?),
+        true => None,
+    };
+    for (index, (bookmark, _)) in jobs.into_iter().enumerate() {
+        match &failed_folder {
+            Some(failed_folder) if failed.contains(&index) => {
+                try_or_report(|| client.move_bookmark(bookmark, failed_folder))
+            }
+            // TODO : Bookmark::delete
+            _ => try_or_report(|| client.delete_bookmark(&bookmark.id)),
+        }
+    }
+    library.save()?;
+    report.print();
 
-    sort::perform(false)?;
+    sort::perform(false, false)?;
 
     Ok(())
 }
 
-fn download_file(link_to_file: &str) -> DonResult<()> {
-    let mut wallpaper = File::create(format!(
-        "{}/{}",
-        CONFIG.wallpapers_dir,
-        link_to_file
-            .split('/')
-            .next_back()
-            .ok_or_don_err("split never returns empty iterator")?
-    ))?;
-    reqwest::blocking::get(link_to_file)?.copy_to(&mut wallpaper)?;
+/// Downloads the first `limit` wallpapers of wallhaven.cc matching `search`, with the API key of
+/// the config if any.
+pub fn fetch_wallhaven(search: &Search, limit: usize) -> DonResult<()> {
+    let http_client = ReqwestClient::new();
+    let wallhaven = Wallhaven::new(CONFIG.wallhaven_api_key.clone());
+    let urls = wallhaven.search(&http_client, search, limit)?;
+    let library = Library::open()?;
+    let catalogue = Catalogue::open()?;
+    let mut report = Report::default();
+
+    let results = run_parallel(
+        &urls,
+        CONFIG.download_workers,
+        |url| url.clone(),
+        |url| download(&http_client, &library, &wallhaven, url),
+    );
+    for (url, result) in urls.iter().zip(results) {
+        match result {
+            Ok(saved) => record(&catalogue, &mut report, saved, url, &wallhaven, None),
+            Err(err) => report.failed.push((url.clone(), err)),
+        }
+    }
+    library.save()?;
+    report.print();
+
+    sort::perform(false, false)?;
 
     Ok(())
 }
+
+fn download(
+    http_client: &dyn HttpClient,
+    library: &Library,
+    downloader: &dyn Downloader,
+    url: &str,
+) -> DonResult<Saved> {
+    let page_url = downloader.page_url(url);
+    let mut source_id = format!("{}_{}", site(downloader, url), base_name(url)?);
+    // The images of a Reddit gallery or an Imgur album share the url of the post
+    if let Some(item) = Url::parse(url)?.fragment() {
+        source_id = format!("{source_id}_{item}");
+    }
+    let scraped = downloader.scrape(http_client, &page_url)?;
+    if let Some(reason) = downloader.rejects(&scraped.metadata) {
+        return Ok(Saved::Skipped(reason));
+    }
+    download_file(http_client, library, scraped, &source_id)
+}
+
+/// The file is first written with a `.part` extension, so that an interrupted download can be
+/// resumed on the next run. It is then checked to be an image, and saved unless the library
+/// already contains the same image.
+fn download_file(
+    http_client: &dyn HttpClient,
+    library: &Library,
+    scraped: Scraped,
+    source_id: &str,
+) -> DonResult<Saved> {
+    let link_to_file = scraped.image_url.as_str();
+    // Named after the url rather than the image, as two images can share the same name
+    let part_path = library
+        .dir
+        .join(format!("{}.part", &hash(link_to_file.as_bytes())[..16]));
+    let mut part_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&part_path)?;
+    let downloaded = http_client.download_to(link_to_file, &mut part_file)?;
+    let bytes = read(&part_path)?;
+    let image = match validate(link_to_file, downloaded.content_type.as_deref(), &bytes) {
+        Ok(image) => image,
+        Err(err) => {
+            // Not worth resuming
+            remove_file(&part_path)?;
+            return Err(err);
+        }
+    };
+
+    let content_hash = hash(&bytes);
+    // Kept locked until the file is saved, so that two workers can't both save the same image
+    let mut content_hashes = library
+        .content_hashes
+        .lock()
+        .expect("No thread panics while holding the lock");
+    if let Some(existing) = content_hashes.find(&content_hash) {
+        let existing = existing.to_owned();
+        remove_file(&part_path)?;
+        return Ok(Saved::Duplicate(existing));
+    }
+    let url_name = base_name(link_to_file)?;
+    let stem = library.naming_strategy.stem(&NameSources {
+        url_name: Path::new(&url_name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(&url_name),
+        source_id,
+        content_hash: &content_hash,
+    });
+    let path = available_path(
+        &library.dir,
+        Path::new(&format!("{stem}.{}", image.extension)),
+    );
+    rename(&part_path, &path)?;
+    content_hashes.insert(path.clone(), content_hash.clone())?;
+
+    Ok(Saved::New(NewFile {
+        path,
+        image_url: scraped.image_url.clone(),
+        metadata: scraped.metadata,
+        content_hash,
+        width: image.width as u32,
+        height: image.height as u32,
+    }))
+}
@@ -0,0 +1,30 @@
+/// How a downloaded wallpaper is named in the wallpapers dir.
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NamingStrategy {
+    /// Last segment of the image url, ex: "wallhaven-zy3l5o.jpg"
+    #[default]
+    UrlName,
+    /// Domain of the site and id of the bookmarked page, ex: "wallhaven.cc_zy3l5o.jpg"
+    SourceId,
+    /// Sha256 of the content of the file
+    ContentHash,
+}
+
+/// What can be used to name a downloaded wallpaper.
+pub(crate) struct NameSources<'l> {
+    /// Base name of the image url, without extension
+    pub(crate) url_name: &'l str,
+    pub(crate) source_id: &'l str,
+    pub(crate) content_hash: &'l str,
+}
+
+impl NamingStrategy {
+    pub(crate) fn stem<'l>(&self, sources: &NameSources<'l>) -> &'l str {
+        match self {
+            NamingStrategy::UrlName => sources.url_name,
+            NamingStrategy::SourceId => sources.source_id,
+            NamingStrategy::ContentHash => sources.content_hash,
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use {
+    don_error::*,
+    std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread,
+    },
+};
+
+pub(crate) const FAILED_FOLDER: &str = "Failed";
+pub(crate) const UNSUPPORTED_DOMAINS_FOLDER: &str = "Unsupported domains";
+
+/// Runs `job` on every item with `workers` threads, printing the progress as items complete.
+/// A failing item doesn't stop the others, and results are returned in the order of `items`.
+pub(crate) fn run_parallel<T: Sync, R: Send>(
+    items: &[T],
+    workers: usize,
+    describe: impl Fn(&T) -> String + Sync,
+    job: impl Fn(&T) -> DonResult<R> + Sync,
+) -> Vec<DonResult<R>> {
+    let next_index = AtomicUsize::new(0);
+    let nb_done = AtomicUsize::new(0);
+    let results = Mutex::new(items.iter().map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next_index.fetch_add(1, Ordering::SeqCst);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = job(item);
+                let nb_done = nb_done.fetch_add(1, Ordering::SeqCst) + 1;
+                match &result {
+                    Ok(_) => println!("[{nb_done}/{}] Downloaded {}", items.len(), describe(item)),
+                    Err(err) => println!(
+                        "[{nb_done}/{}] Failed to download {} : {err}",
+                        items.len(),
+                        describe(item)
+                    ),
+                }
+                results
+                    .lock()
+                    .expect("No thread panics while holding the lock")[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .expect("No thread panics while holding the lock")
+        .into_iter()
+        .map(|result| result.expect("Every item has been processed"))
+        .collect()
+}
+
+#[derive(Default)]
+pub(crate) struct Report {
+    pub(crate) nb_downloaded: usize,
+    pub(crate) duplicates: Vec<(String, PathBuf)>,
+    /// With the reason why
+    pub(crate) skipped: Vec<(String, String)>,
+    pub(crate) failed: Vec<(String, DonError)>,
+    pub(crate) unsupported: Vec<String>,
+    /// Whether the urls came from bookmarks, which are moved to folders when they fail
+    pub(crate) from_bookmarks: bool,
+}
+
+impl Report {
+    pub(crate) fn print(&self) {
+        println!(
+            "Downloaded {} wallpaper(s), {} duplicate(s), {} skipped, {} failed, {} unsupported",
+            self.nb_downloaded,
+            self.duplicates.len(),
+            self.skipped.len(),
+            self.failed.len(),
+            self.unsupported.len()
+        );
+        if !self.duplicates.is_empty() {
+            println!("Already in the library :");
+            self.duplicates
+                .iter()
+                .for_each(|(url, existing)| println!("  - {url} : {existing:?}"));
+        }
+        if !self.skipped.is_empty() {
+            println!("Skipped :");
+            self.skipped
+                .iter()
+                .for_each(|(url, reason)| println!("  - {url} : {reason}"));
+        }
+        if !self.failed.is_empty() {
+            match self.from_bookmarks {
+                true => println!("Failed (moved to '{FAILED_FOLDER}') :"),
+                false => println!("Failed :"),
+            }
+            self.failed
+                .iter()
+                .for_each(|(url, err)| println!("  - {url} : {err}"));
+        }
+        if !self.unsupported.is_empty() {
+            println!("Unsupported (moved to '{UNSUPPORTED_DOMAINS_FOLDER}') :");
+            self.unsupported
+                .iter()
+                .for_each(|url| println!("  - {url}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run_parallel_keeps_going_after_failures() {
+        let items = (0..20).collect::<Vec<_>>();
+        let results = run_parallel(
+            &items,
+            4,
+            |item| item.to_string(),
+            |item| match item % 3 {
+                0 => bail!("{item} is a multiple of 3"),
+                _ => Ok(()),
+            },
+        );
+        assert_eq!(results.len(), items.len());
+        for (item, result) in items.iter().zip(results) {
+            assert_eq!(result.is_err(), item % 3 == 0, "{item}");
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use super::{direct::is_image_url, Downloader, HttpClient, Metadata, Scraped};
+
+use {
+    don_error::*,
+    serde::{de::IgnoredAny, Deserialize},
+    std::collections::HashMap,
+    url::Url,
+};
+
+/// Posts of Reddit, through the JSON the site serves for any page when `.json` is appended to it.
+/// Galleries are expanded into their images, the id of each one as the fragment of the url.
+pub(crate) struct Reddit;
+
+#[derive(Deserialize)]
+struct Listing {
+    data: ListingData,
+}
+
+#[derive(Deserialize)]
+struct ListingData {
+    children: Vec<Child>,
+}
+
+#[derive(Deserialize)]
+struct Child {
+    data: Post,
+}
+
+#[derive(Deserialize)]
+struct Post {
+    title: String,
+    author: String,
+    /// What the post links to
+    url: String,
+    gallery_data: Option<GalleryData>,
+    media_metadata: Option<HashMap<String, Media>>,
+    /// The original post, when this one is a crosspost
+    #[serde(default)]
+    crosspost_parent_list: Vec<Post>,
+}
+
+#[derive(Deserialize)]
+struct GalleryData {
+    items: Vec<GalleryItem>,
+}
+
+#[derive(Deserialize)]
+struct GalleryItem {
+    media_id: String,
+}
+
+#[derive(Deserialize)]
+struct Media {
+    /// Ex: "image/jpg"
+    m: Option<String>,
+}
+
+impl Post {
+    /// Ids and urls of the images of a gallery, in order.
+    fn gallery(&self) -> Vec<(&str, String)> {
+        let Some(gallery_data) = &self.gallery_data else {
+            return vec![];
+        };
+        gallery_data
+            .items
+            .iter()
+            .map(|item| {
+                let extension = self
+                    .media_metadata
+                    .as_ref()
+                    .and_then(|media_metadata| media_metadata.get(&item.media_id)?.m.as_deref())
+                    .and_then(|mime_type| mime_type.strip_prefix("image/"))
+                    .unwrap_or("jpg");
+                (
+                    item.media_id.as_str(),
+                    format!("https://i.redd.it/{}.{extension}", item.media_id),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Reddit {
+    fn fetch_post(&self, client: &dyn HttpClient, url: &Url) -> DonResult<Post> {
+        let json_url = match url.domain() {
+            // Short links, ex: "https://redd.it/1c2d3e4"
+            Some("redd.it") => format!(
+                "https://www.reddit.com/comments{}.json",
+                url.path().trim_end_matches('/')
+            ),
+            _ => format!(
+                "{}://{}{}.json",
+                url.scheme(),
+                url.host_str().unwrap_or("www.reddit.com"),
+                url.path().trim_end_matches('/')
+            ),
+        };
+        let (listing, _comments): (Listing, IgnoredAny) =
+            serde_json::from_str(&self.fetch_page(client, &json_url)?)
+                .err_ctx_val("url", json_url.as_str())?;
+        let post = listing
+            .data
+            .children
+            .into_iter()
+            .next()
+            .ok_or_don_err(format!("No post at {url}"))?
+            .data;
+        Ok(match post.crosspost_parent_list.is_empty() {
+            true => post,
+            false => post
+                .crosspost_parent_list
+                .into_iter()
+                .next()
+                .expect("The list isn't empty"),
+        })
+    }
+}
+
+impl Downloader for Reddit {
+    fn domains(&self) -> Vec<&str> {
+        vec!["reddit.com", "redd.it"]
+    }
+
+    fn expand(&self, client: &dyn HttpClient, url: &str) -> DonResult<Vec<String>> {
+        let mut url = Url::parse(url)?;
+        if is_image_url(&url) {
+            return Ok(vec![url.to_string()]);
+        }
+        url.set_fragment(None);
+        let post = self.fetch_post(client, &url)?;
+        let gallery = post.gallery();
+        Ok(match gallery.is_empty() {
+            true => vec![url.to_string()],
+            false => gallery
+                .into_iter()
+                .map(|(id, _)| format!("{url}#{id}"))
+                .collect(),
+        })
+    }
+
+    fn scrape(&self, client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped> {
+        let mut url = Url::parse(page_url)?;
+        // Ex: "https://i.redd.it/a1b2c3d4e5f6.jpg"
+        if is_image_url(&url) {
+            return Ok(Scraped {
+                image_url: page_url.to_string(),
+                metadata: Metadata::default(),
+            });
+        }
+        let media_id = url.fragment().map(str::to_string);
+        url.set_fragment(None);
+        let post = self.fetch_post(client, &url)?;
+        let gallery = post.gallery();
+        let image_url = match (media_id, gallery.first()) {
+            (Some(media_id), _) => gallery
+                .iter()
+                .find(|(id, _)| *id == media_id)
+                .map(|(_, image_url)| image_url.clone())
+                .ok_or_don_err(format!("No image {media_id} in the gallery"))?,
+            (None, Some((_, image_url))) => image_url.clone(),
+            (None, None) if is_image_url(&Url::parse(&post.url)?) => post.url.clone(),
+            (None, None) => bail!("The post links to {}, which isn't an image", post.url),
+        };
+        Ok(Scraped {
+            image_url,
+            metadata: Metadata {
+                title: Some(post.title),
+                author: Some(format!("u/{}", post.author)),
+                licence: None,
+                tags: vec![],
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::test_helpers::FakeClient, *};
+
+    const POST_URL: &str =
+        "https://www.reddit.com/r/wallpaper/comments/1c2d3e4/lofoten_at_night_3840x2160/";
+    const CROSSPOST_URL: &str =
+        "https://www.reddit.com/r/WidescreenWallpaper/comments/1f2g3h4/dunes_in_329/";
+
+    fn client() -> FakeClient {
+        FakeClient::new(&[
+            (
+                "https://www.reddit.com/r/wallpaper/comments/1c2d3e4/lofoten_at_night_3840x2160.json",
+                include_str!("fixtures/reddit_post.json"),
+            ),
+            (
+                "https://www.reddit.com/comments/1c2d3e4.json",
+                include_str!("fixtures/reddit_post.json"),
+            ),
+            (
+                "https://www.reddit.com/r/WidescreenWallpaper/comments/1f2g3h4/dunes_in_329.json",
+                include_str!("fixtures/reddit_gallery.json"),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_image_post() {
+        let client = client();
+        assert_eq!(Reddit.expand(&client, POST_URL).unwrap(), [POST_URL]);
+        let expected = Scraped {
+            image_url: "https://i.redd.it/a1b2c3d4e5f6.jpg".to_string(),
+            metadata: Metadata {
+                title: Some("Lofoten at night [3840x2160]".to_string()),
+                author: Some("u/jdoe".to_string()),
+                ..Default::default()
+            },
+        };
+        assert_eq!(Reddit.scrape(&client, POST_URL).unwrap(), expected);
+        assert_eq!(
+            Reddit.scrape(&client, "https://redd.it/1c2d3e4").unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_gallery_of_crosspost() {
+        let client = client();
+        let images = Reddit.expand(&client, CROSSPOST_URL).unwrap();
+        assert_eq!(
+            images,
+            [
+                format!("{CROSSPOST_URL}#x9y8z7w6v5u4"),
+                format!("{CROSSPOST_URL}#q1w2e3r4t5y6"),
+            ]
+        );
+        let scraped = Reddit.scrape(&client, &images[1]).unwrap();
+        assert_eq!(scraped.image_url, "https://i.redd.it/q1w2e3r4t5y6.png");
+        // Credited to the author of the original post
+        assert_eq!(scraped.metadata.author.as_deref(), Some("u/jdoe"));
+        assert!(Reddit
+            .scrape(&client, &format!("{CROSSPOST_URL}#missing"))
+            .is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use super::{extract_attr, extract_text, extract_texts, Downloader, HttpClient, Metadata, Scraped};
+
+use {don_error::*, scraper::Html, url::Url};
+
+/// A site described in the config file instead of code.
+///
+/// ```toml
+/// [[scraping_rules]]
+/// domain = "wallpaperflare.com"
+/// rewrites = [{ strip_suffix = "/" }, { strip_suffix = "/download" }]
+/// steps = [
+///     { selector = "a.link_btn.aq.mt20", attr = "href" },
+///     { selector = "img#show_img", attr = "src" },
+/// ]
+/// metadata = { title = "h1[itemprop=name]", tags = "ul.tag_list li" }
+/// ```
+#[derive(Debug, Clone, serde::Deserialize)]
+pub(crate) struct ScrapingRule {
+    /// Domain handled by the rule, subdomains included.
+    pub(crate) domain: String,
+    /// Applied in order to the bookmarked url to get the url of the first page to scrape.
+    #[serde(default)]
+    pub(crate) rewrites: Vec<UrlRewrite>,
+    /// Each step reads an url from the current page, the last one being the url of the image.
+    pub(crate) steps: Vec<ScrapingStep>,
+    /// Where the metadata is on the first page
+    #[serde(default)]
+    pub(crate) metadata: MetadataSelectors,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UrlRewrite {
+    StripSuffix(String),
+    AppendSuffix(String),
+    Replace { from: String, to: String },
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub(crate) struct ScrapingStep {
+    pub(crate) selector: String,
+    pub(crate) attr: String,
+}
+
+/// Selectors of the tags whose text is the metadata.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub(crate) struct MetadataSelectors {
+    pub(crate) title: Option<String>,
+    pub(crate) author: Option<String>,
+    pub(crate) licence: Option<String>,
+    /// Matches every tag
+    pub(crate) tags: Option<String>,
+}
+
+impl MetadataSelectors {
+    fn extract(&self, page: &str) -> DonResult<Metadata> {
+        let html = Html::parse_document(page);
+        let text = |selector: &Option<String>| match selector {
+            Some(selector) => extract_text(&html, selector),
+            None => Ok(None),
+        };
+        Ok(Metadata {
+            title: text(&self.title)?,
+            author: text(&self.author)?,
+            licence: text(&self.licence)?,
+            tags: match &self.tags {
+                Some(selector) => extract_texts(&html, selector)?,
+                None => vec![],
+            },
+        })
+    }
+}
+
+impl UrlRewrite {
+    fn apply(&self, url: &str) -> String {
+        match self {
+            UrlRewrite::StripSuffix(suffix) => {
+                url.strip_suffix(suffix.as_str()).unwrap_or(url).to_string()
+            }
+            UrlRewrite::AppendSuffix(suffix) => format!("{url}{suffix}"),
+            UrlRewrite::Replace { from, to } => url.replace(from.as_str(), to),
+        }
+    }
+}
+
+impl Downloader for ScrapingRule {
+    fn domains(&self) -> Vec<&str> {
+        vec![&self.domain]
+    }
+
+    fn page_url(&self, url: &str) -> String {
+        self.rewrites
+            .iter()
+            .fold(url.to_string(), |url, rewrite| rewrite.apply(&url))
+    }
+
+    fn scrape(&self, client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped> {
+        if self.steps.is_empty() {
+            bail!(
+                "The scraping rule for '{}' doesn't have any step",
+                self.domain
+            );
+        }
+        let mut url = page_url.to_string();
+        let mut metadata = None;
+        for step in &self.steps {
+            let page = self.fetch_page(client, &url)?;
+            if metadata.is_none() {
+                metadata = Some(self.metadata.extract(&page)?);
+            }
+            let extracted = extract_attr(&page, &step.selector, &step.attr)?;
+            // Links are often relative to the page they were found in
+            url = Url::parse(&url)?.join(&extracted)?.to_string();
+        }
+        Ok(Scraped {
+            image_url: url,
+            metadata: metadata.unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::test_helpers::FakeClient, *};
+
+    fn wallpaper_flare_rule() -> ScrapingRule {
+        ScrapingRule {
+            domain: "wallpaperflare.com".to_string(),
+            rewrites: vec![
+                UrlRewrite::StripSuffix("/".to_string()),
+                UrlRewrite::StripSuffix("/download".to_string()),
+            ],
+            steps: vec![
+                ScrapingStep {
+                    selector: "a.link_btn.aq.mt20".to_string(),
+                    attr: "href".to_string(),
+                },
+                ScrapingStep {
+                    selector: "img#show_img".to_string(),
+                    attr: "src".to_string(),
+                },
+            ],
+            metadata: MetadataSelectors {
+                title: Some("h1[itemprop=name]".to_string()),
+                tags: Some("ul.tag_list li".to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_rewrites_are_applied_in_order() {
+        let rule = ScrapingRule {
+            domain: "flickr.com".to_string(),
+            rewrites: vec![
+                UrlRewrite::StripSuffix("/".to_string()),
+                UrlRewrite::AppendSuffix("/sizes/o".to_string()),
+                UrlRewrite::Replace {
+                    from: "http://".to_string(),
+                    to: "https://".to_string(),
+                },
+            ],
+            steps: vec![],
+            metadata: MetadataSelectors::default(),
+        };
+        assert_eq!(
+            rule.page_url("http://www.flickr.com/photos/jdoe/52871234567/"),
+            "https://www.flickr.com/photos/jdoe/52871234567/sizes/o"
+        );
+    }
+
+    #[test]
+    fn test_multi_hop_steps() {
+        let page_url =
+            "https://www.wallpaperflare.com/mountain-lake-reflection-nature-landscape-wallpaper-pxzyg";
+        let rule = wallpaper_flare_rule();
+        let client = FakeClient::new(&[
+            (page_url, include_str!("fixtures/wallpaper_flare_page.html")),
+            (
+                &format!("{page_url}/download"),
+                include_str!("fixtures/wallpaper_flare_download.html"),
+            ),
+        ]);
+        assert_eq!(rule.page_url(&format!("{page_url}/download/")), page_url);
+        let scraped = rule.scrape(&client, page_url).unwrap();
+        assert_eq!(
+            scraped.image_url,
+            "https://c4.wallpaperflare.com/wallpaper/1002/833/102/mountain-lake-reflection-nature-landscape-wallpaper.jpg"
+        );
+        // Taken from the first page
+        assert_eq!(
+            scraped.metadata.title.as_deref(),
+            Some("mountain lake reflection")
+        );
+        assert_eq!(scraped.metadata.tags, ["mountain", "lake"]);
+    }
+
+    #[test]
+    fn test_relative_links_are_resolved() {
+        let rule = ScrapingRule {
+            domain: "example.com".to_string(),
+            rewrites: vec![],
+            steps: vec![ScrapingStep {
+                selector: "img#wallpaper".to_string(),
+                attr: "src".to_string(),
+            }],
+            metadata: MetadataSelectors::default(),
+        };
+        let client = FakeClient::new(&[(
+            "https://example.com/wallpapers/42",
+            r#"<html><body><img id="wallpaper" src="../full/42.png"></body></html>"#,
+        )]);
+        assert_eq!(
+            rule.scrape(&client, "https://example.com/wallpapers/42")
+                .unwrap()
+                .image_url,
+            "https://example.com/full/42.png"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use super::{Downloader, HttpClient, Metadata, Scraped};
+
+use {
+    don_error::*,
+    serde::{de::DeserializeOwned, Deserialize},
+    url::Url,
+};
+
+const API_URL: &str = "https://api.unsplash.com";
+
+/// All the photos of Unsplash are under the same licence.
+const LICENCE: &str = "Unsplash License";
+
+/// Length of the ids of the photos, which end the slugs of their pages.
+const ID_LENGTH: usize = 11;
+
+/// Photos of Unsplash. With the access key of an application registered on Unsplash, their
+/// description, author and tags are read from the API. Otherwise only the image is downloaded.
+///
+/// ```toml
+/// [unsplash]
+/// access_key = "0123456789abcdef"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct Unsplash {
+    #[serde(default)]
+    access_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Photo {
+    description: Option<String>,
+    alt_description: Option<String>,
+    user: User,
+    #[serde(default)]
+    tags: Vec<Tag>,
+    links: Links,
+}
+
+#[derive(Deserialize)]
+struct User {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct Links {
+    /// To be requested on each download, as asked by the API guidelines
+    download_location: String,
+}
+
+#[derive(Deserialize)]
+struct Download {
+    url: String,
+}
+
+/// The id of the photo in the url of its page (ex: "/photos/a-forest-in-the-fog-Xy1Z2abCdEf").
+fn photo_id(url: &Url) -> Option<String> {
+    let segments = url
+        .path_segments()?
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    match segments[..] {
+        ["photos", slug, ..] => Some(slug.get(slug.len().checked_sub(ID_LENGTH)?..)?.to_string()),
+        _ => None,
+    }
+}
+
+impl Unsplash {
+    fn api<T: DeserializeOwned>(
+        &self,
+        client: &dyn HttpClient,
+        url: &str,
+        access_key: &str,
+    ) -> DonResult<T> {
+        let authorization = format!("Client-ID {access_key}");
+        let text = client.get_text_with_headers(url, &[("Authorization", &authorization)])?;
+        serde_json::from_str(&text).err_ctx_val("url", url)
+    }
+}
+
+impl Downloader for Unsplash {
+    fn domains(&self) -> Vec<&str> {
+        vec!["unsplash.com"]
+    }
+
+    fn scrape(&self, client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped> {
+        let url = Url::parse(page_url)?;
+        // Links to the image itself, which have no extension
+        if url.domain() == Some("images.unsplash.com") {
+            return Ok(Scraped {
+                image_url: page_url.to_string(),
+                metadata: Metadata {
+                    licence: Some(LICENCE.to_string()),
+                    ..Default::default()
+                },
+            });
+        }
+        let id = photo_id(&url).ok_or_don_err(format!("Not an Unsplash photo : {page_url}"))?;
+        let Some(access_key) = &self.access_key else {
+            return Ok(Scraped {
+                image_url: format!("https://unsplash.com/photos/{id}/download"),
+                metadata: Metadata {
+                    licence: Some(LICENCE.to_string()),
+                    ..Default::default()
+                },
+            });
+        };
+        let photo: Photo = self.api(client, &format!("{API_URL}/photos/{id}"), access_key)?;
+        let download: Download = self.api(client, &photo.links.download_location, access_key)?;
+        Ok(Scraped {
+            image_url: download.url,
+            metadata: Metadata {
+                title: photo.description.or(photo.alt_description),
+                author: Some(photo.user.name),
+                licence: Some(LICENCE.to_string()),
+                tags: photo.tags.into_iter().map(|tag| tag.title).collect(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::test_helpers::FakeClient, *};
+
+    const PAGE_URL: &str = "https://unsplash.com/photos/a-forest-in-the-fog-Xy1Z2abCdEf";
+
+    #[test]
+    fn test_photo_id() {
+        for url in [
+            PAGE_URL,
+            "https://unsplash.com/photos/Xy1Z2abCdEf",
+            "https://unsplash.com/photos/Xy1Z2abCdEf/download?force=true",
+        ] {
+            assert_eq!(
+                photo_id(&Url::parse(url).unwrap()).as_deref(),
+                Some("Xy1Z2abCdEf"),
+                "{url}"
+            );
+        }
+        assert_eq!(
+            photo_id(&Url::parse("https://unsplash.com/@jdoe").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_scrape_with_api() {
+        let client = FakeClient::new(&[
+            (
+                "https://api.unsplash.com/photos/Xy1Z2abCdEf",
+                include_str!("fixtures/unsplash_photo.json"),
+            ),
+            (
+                "https://api.unsplash.com/photos/Xy1Z2abCdEf/download?ixid=M3w1",
+                include_str!("fixtures/unsplash_download.json"),
+            ),
+        ]);
+        let unsplash = Unsplash {
+            access_key: Some("k3y".to_string()),
+        };
+        assert_eq!(
+            unsplash.scrape(&client, PAGE_URL).unwrap(),
+            Scraped {
+                image_url: "https://images.unsplash.com/photo-1709372841-1a2b3c4d5e6f?ixid=M3w1&fm=jpg&q=85&fit=crop&dl=a-forest-in-the-fog.jpg".to_string(),
+                metadata: Metadata {
+                    title: Some("a forest in the fog".to_string()),
+                    author: Some("Jane Doe".to_string()),
+                    licence: Some(LICENCE.to_string()),
+                    tags: vec!["forest".to_string(), "fog".to_string()],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_scrape_without_api() {
+        let client = FakeClient::new(&[]);
+        assert_eq!(
+            Unsplash::default().scrape(&client, PAGE_URL).unwrap(),
+            Scraped {
+                image_url: "https://unsplash.com/photos/Xy1Z2abCdEf/download".to_string(),
+                metadata: Metadata {
+                    licence: Some(LICENCE.to_string()),
+                    ..Default::default()
+                },
+            }
+        );
+        assert!(Unsplash::default()
+            .scrape(&client, "https://unsplash.com/@jdoe")
+            .is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use {
+    don_error::*,
+    imagesize::{blob_size, image_type, ImageType},
+    url::Url,
+};
+
+/// Number of bytes of a rejected download shown in the error, usually the start of an html page.
+const PREVIEW_LEN: usize = 200;
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct ValidImage {
+    pub(crate) extension: &'static str,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+}
+
+/// Checks that the downloaded bytes are an image we can display, looking at the magic bytes and
+/// the dimensions rather than trusting the url or the server.
+pub(crate) fn validate(
+    url: &str,
+    content_type: Option<&str>,
+    bytes: &[u8],
+) -> DonResult<ValidImage> {
+    let err_ctx = DonErrorContext::new()
+        .with_ser("url", url)
+        .with_ser("content type", content_type)
+        .with_ser("size", bytes.len())
+        .with_ser(
+            "start of content",
+            String::from_utf8_lossy(&bytes[..bytes.len().min(PREVIEW_LEN)]),
+        );
+
+    if let Some(content_type) = content_type {
+        let is_binary = content_type.starts_with("image/")
+            || content_type.starts_with("application/octet-stream")
+            || content_type.starts_with("binary/octet-stream");
+        if !is_binary {
+            return Err(err_msg!("Unexpected content type '{content_type}'").with_ctx(&err_ctx));
+        }
+    }
+    let extension = match image_type(bytes)
+        .map_err(|err| err_msg!("Not a recognized image : {err:?}"))
+        .err_ctx(&err_ctx)?
+    {
+        ImageType::Jpeg => "jpg",
+        ImageType::Png => "png",
+        ImageType::Gif => "gif",
+        ImageType::Webp => "webp",
+        ImageType::Bmp => "bmp",
+        ImageType::Tiff => "tiff",
+        other => {
+            return Err(err_msg!("Unsupported image format {other:?}").with_ctx(&err_ctx));
+        }
+    };
+    let size = blob_size(bytes)
+        .map_err(|err| err_msg!("Can't read the dimensions of the image : {err:?}"))
+        .err_ctx(&err_ctx)?;
+    if size.width == 0 || size.height == 0 {
+        return Err(err_msg!("Image has an empty dimension").with_ctx(&err_ctx));
+    }
+
+    Ok(ValidImage {
+        extension,
+        width: size.width,
+        height: size.height,
+    })
+}
+
+/// Last non empty segment of the url path, without query string nor fragment.
+pub(crate) fn base_name(url: &str) -> DonResult<String> {
+    let url = Url::parse(url)?;
+    Ok(url
+        .path_segments()
+        .and_then(|mut segments| segments.rfind(|segment| !segment.is_empty()))
+        .unwrap_or("wallpaper")
+        .to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// Smallest valid png, 1x1 pixel.
+    pub(crate) const PNG_1X1: &[u8] = &[
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F,
+        0x15, 0xC4, 0x89, 0x00, 0x00, 0x00, 0x0A, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0x00,
+        0x01, 0x00, 0x00, 0x05, 0x00, 0x01, 0x0D, 0x0A, 0x2D, 0xB4, 0x00, 0x00, 0x00, 0x00, 0x49,
+        0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn test_valid_png() {
+        assert_eq!(
+            validate("https://example.com/a", Some("image/png"), PNG_1X1).unwrap(),
+            ValidImage {
+                extension: "png",
+                width: 1,
+                height: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_html_error_page_is_rejected() {
+        let page = b"<!DOCTYPE html><html><body>Access denied</body></html>";
+        let err = validate("https://example.com/a.jpg", Some("text/html"), page).unwrap_err();
+        assert!(err.to_string().contains("text/html"));
+        assert!(err.context.contains_key("start of content"));
+        // Even if the server lies about the content type
+        assert!(validate("https://example.com/a.jpg", Some("image/jpeg"), page).is_err());
+    }
+
+    #[test]
+    fn test_truncated_image_is_rejected() {
+        assert!(validate("https://example.com/a.png", None, &PNG_1X1[..12]).is_err());
+    }
+
+    #[test]
+    fn test_base_name_strips_query_string() {
+        assert_eq!(
+            base_name("https://images.example.com/full/abc.jpg?w=3840&q=80#top").unwrap(),
+            "abc.jpg"
+        );
+        assert_eq!(
+            base_name("https://images.example.com/").unwrap(),
+            "wallpaper"
+        );
+    }
+}
@@ -1,24 +1,271 @@
-use don_error::*;
-
-pub(crate) fn download(url: &str) -> DonResult<()> {
-    println!("Downloading from {url}");
-
-    let source_code = reqwest::blocking::get(url)?.text()?;
-    let html = scraper::Html::parse_document(&source_code);
-    let selector_str = "div#allsizes-photo>img";
-    let err_ctx = DonErrorContext::new().with_ser("source code", &source_code);
-
-    super::download_file(
-        html.select(
-            &scraper::Selector::parse(selector_str)
-                .map_err(|_| err_msg!("Failed to create the selector for '{selector_str}'"))?,
-        )
-        .next()
-        .ok_or_don_err("Couldn't find any tag 'img' with id 'wallpaper'")
-        .err_ctx(&err_ctx)?
-        .value()
-        .attr("data-cfsrc")
-        .ok_or_don_err("The tag was found but doesn't contain an attribute 'data-cfsrc'")
-        .err_ctx(&err_ctx)?,
-    )
+use super::{Downloader, HttpClient, Metadata, Scraped};
+
+use {don_error::*, serde::Deserialize, url::Url};
+
+const API_URL: &str = "https://wallhaven.cc/api/v1";
+
+/// Wallhaven, through its JSON API rather than its pages. The API key of the account is only
+/// needed for NSFW wallpapers and to search with the account's settings. It's sent in a header,
+/// so that it doesn't show in the urls of the reports.
+pub(crate) struct Wallhaven {
+    api_url: String,
+    api_key: Option<String>,
+}
+
+/// What `wall fetch wallhaven` looks for, every criterion being optional.
+#[derive(Debug, Default)]
+pub struct Search {
+    pub query: Option<String>,
+    /// Ex: "16x9", "32x9"
+    pub ratios: Vec<String>,
+    /// Ex: "2560x1440"
+    pub min_resolution: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct Wallpaper {
+    /// Of the image
+    path: String,
+    uploader: Option<Uploader>,
+    #[serde(default)]
+    tags: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+struct Uploader {
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SearchPage {
+    data: Vec<SearchResult>,
+    meta: SearchMeta,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    /// Of the page of the wallpaper
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct SearchMeta {
+    current_page: u32,
+    last_page: u32,
+}
+
+impl Wallhaven {
+    pub(crate) fn new(api_key: Option<String>) -> Self {
+        Wallhaven {
+            api_url: API_URL.to_string(),
+            api_key,
+        }
+    }
+
+    fn api(&self, endpoint: &str, params: &[(&str, String)]) -> DonResult<String> {
+        let mut url = Url::parse(&format!("{}/{endpoint}", self.api_url))?;
+        url.query_pairs_mut().extend_pairs(params);
+        // No empty "?" when there is no parameter
+        if url.query() == Some("") {
+            url.set_query(None);
+        }
+        Ok(url.to_string())
+    }
+
+    /// Urls of the pages of the first `limit` wallpapers matching `search`.
+    pub(crate) fn search(
+        &self,
+        client: &dyn HttpClient,
+        search: &Search,
+        limit: usize,
+    ) -> DonResult<Vec<String>> {
+        let mut params = vec![];
+        if let Some(query) = &search.query {
+            params.push(("q", query.clone()));
+        }
+        if !search.ratios.is_empty() {
+            params.push(("ratios", search.ratios.join(",")));
+        }
+        if let Some(min_resolution) = &search.min_resolution {
+            params.push(("atleast", min_resolution.clone()));
+        }
+
+        let mut urls = vec![];
+        let mut page = 1;
+        while urls.len() < limit {
+            let mut page_params = params.clone();
+            page_params.push(("page", page.to_string()));
+            let url = self.api("search", &page_params)?;
+            let results: SearchPage = serde_json::from_str(&self.fetch_page(client, &url)?)
+                .err_ctx_val("url", url.as_str())?;
+            urls.extend(results.data.into_iter().map(|result| result.url));
+            if results.meta.current_page >= results.meta.last_page {
+                break;
+            }
+            page += 1;
+        }
+        urls.truncate(limit);
+        Ok(urls)
+    }
+}
+
+/// The id of the wallpaper in the url of its page, short url or image url.
+fn wallpaper_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let last_segment = url.path_segments()?.next_back()?;
+    let id = last_segment
+        .strip_prefix("wallhaven-")
+        .unwrap_or(last_segment);
+    let id = id.split('.').next()?;
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+impl Downloader for Wallhaven {
+    fn domains(&self) -> Vec<&str> {
+        vec!["wallhaven.cc", "whvn.cc"]
+    }
+
+    fn fetch_page(&self, client: &dyn HttpClient, url: &str) -> DonResult<String> {
+        let headers = self
+            .api_key
+            .iter()
+            .map(|api_key| ("X-API-Key", api_key.as_str()))
+            .collect::<Vec<_>>();
+        client.get_text_with_headers(url, &headers)
+    }
+
+    /// The url of the wallpaper in the API, or the url itself when it doesn't contain an id.
+    fn page_url(&self, url: &str) -> String {
+        wallpaper_id(url)
+            .and_then(|id| self.api(&format!("w/{id}"), &[]).ok())
+            .unwrap_or_else(|| url.to_string())
+    }
+
+    fn scrape(&self, client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped> {
+        let response: Response<Wallpaper> =
+            serde_json::from_str(&self.fetch_page(client, page_url)?)
+                .err_ctx_val("url", page_url)?;
+        let wallpaper = response.data;
+        // Wallpapers have neither a title nor a licence on Wallhaven
+        Ok(Scraped {
+            image_url: wallpaper.path,
+            metadata: Metadata {
+                title: None,
+                author: wallpaper.uploader.map(|uploader| uploader.username),
+                licence: None,
+                tags: wallpaper.tags.into_iter().map(|tag| tag.name).collect(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        super::{
+            test_helpers::{serve, FakeClient},
+            ReqwestClient,
+        },
+        *,
+    };
+
+    const API_WALLPAPER_URL: &str = "https://wallhaven.cc/api/v1/w/zy3l5o";
+
+    #[test]
+    fn test_page_url_points_to_api() {
+        for url in [
+            "https://wallhaven.cc/w/zy3l5o",
+            "https://whvn.cc/zy3l5o",
+            "https://w.wallhaven.cc/full/zy/wallhaven-zy3l5o.jpg",
+        ] {
+            assert_eq!(
+                Wallhaven::new(None).page_url(url),
+                API_WALLPAPER_URL,
+                "{url}"
+            );
+        }
+        // The key isn't in the url
+        assert_eq!(
+            Wallhaven::new(Some("s3cr3t".to_string())).page_url("https://wallhaven.cc/w/zy3l5o"),
+            API_WALLPAPER_URL
+        );
+    }
+
+    #[test]
+    fn test_scrape() {
+        let client = FakeClient::new(&[(
+            API_WALLPAPER_URL,
+            include_str!("fixtures/wallhaven_wallpaper.json"),
+        )]);
+        assert_eq!(
+            Wallhaven::new(None)
+                .scrape(&client, API_WALLPAPER_URL)
+                .unwrap(),
+            Scraped {
+                image_url: "https://w.wallhaven.cc/full/zy/wallhaven-zy3l5o.jpg".to_string(),
+                metadata: Metadata {
+                    author: Some("jdoe".to_string()),
+                    tags: vec!["nature".to_string(), "landscape".to_string()],
+                    ..Default::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_scrape_fails_on_unexpected_page() {
+        let client = FakeClient::new(&[(
+            API_WALLPAPER_URL,
+            include_str!("fixtures/flickr_sizes.html"),
+        )]);
+        assert!(Wallhaven::new(None)
+            .scrape(&client, API_WALLPAPER_URL)
+            .is_err());
+    }
+
+    #[test]
+    fn test_search_over_http() {
+        let base_url = serve(vec![
+            (
+                "/search?q=forest&ratios=16x9%2C21x9&atleast=2560x1440&page=1",
+                include_str!("fixtures/wallhaven_search_1.json").to_string(),
+            ),
+            (
+                "/search?q=forest&ratios=16x9%2C21x9&atleast=2560x1440&page=2",
+                include_str!("fixtures/wallhaven_search_2.json").to_string(),
+            ),
+        ]);
+        let wallhaven = Wallhaven {
+            api_url: base_url,
+            api_key: Some("s3cr3t".to_string()),
+        };
+        let search = Search {
+            query: Some("forest".to_string()),
+            ratios: vec!["16x9".to_string(), "21x9".to_string()],
+            min_resolution: Some("2560x1440".to_string()),
+        };
+        let client = ReqwestClient::new();
+
+        assert_eq!(
+            wallhaven.search(&client, &search, 10).unwrap(),
+            [
+                "https://wallhaven.cc/w/zy3l5o",
+                "https://wallhaven.cc/w/8oev1j",
+                "https://wallhaven.cc/w/rr7zqm"
+            ]
+        );
+        assert_eq!(wallhaven.search(&client, &search, 1).unwrap().len(), 1);
+        // Not served
+        assert!(wallhaven.search(&client, &Search::default(), 1).is_err());
+    }
 }
@@ -1,45 +1,124 @@
-use don_error::*;
+use super::{extract_attr, extract_text, extract_texts, Downloader, HttpClient, Metadata, Scraped};
 
-pub(crate) fn download(mut url: &str) -> DonResult<()> {
-    for suffix in ["download", "download/"] {
-        url = url.strip_suffix(suffix).unwrap_or(url);
+use {don_error::*, scraper::Html, url::Url};
+
+pub(crate) struct WallpaperFlare;
+
+impl Downloader for WallpaperFlare {
+    fn domains(&self) -> Vec<&str> {
+        vec!["wallpaperflare.com"]
+    }
+
+    fn page_url(&self, mut url: &str) -> String {
+        for suffix in ["download", "download/"] {
+            url = url.strip_suffix(suffix).unwrap_or(url);
+        }
+        url.to_string()
+    }
+
+    fn scrape(&self, client: &dyn HttpClient, page_url: &str) -> DonResult<Scraped> {
+        let page = self.fetch_page(client, page_url)?;
+        let link_to_download_page =
+            Url::parse(page_url)?.join(&extract_attr(&page, "a.link_btn.aq.mt20", "href")?)?;
+        let image_url = extract_attr(
+            &self.fetch_page(client, link_to_download_page.as_str())?,
+            "img#show_img",
+            "src",
+        )?;
+        // Wallpapers are uploaded anonymously
+        let html = Html::parse_document(&page);
+        Ok(Scraped {
+            image_url,
+            metadata: Metadata {
+                title: extract_text(&html, "h1[itemprop=name]")?,
+                author: None,
+                licence: extract_text(&html, "[itemprop=license]")?,
+                tags: extract_texts(&html, "ul.tag_list li[itemprop=keywords]")?,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        super::{
+            test_helpers::{serve, FakeClient},
+            ReqwestClient,
+        },
+        *,
+    };
+
+    const PAGE_URL: &str =
+        "https://www.wallpaperflare.com/mountain-lake-reflection-nature-landscape-wallpaper-pxzyg";
+    const IMAGE_URL: &str = "https://c4.wallpaperflare.com/wallpaper/1002/833/102/mountain-lake-reflection-nature-landscape-wallpaper.jpg";
+
+    #[test]
+    fn test_page_url_strips_download_suffix() {
+        assert_eq!(
+            WallpaperFlare.page_url(&format!("{PAGE_URL}/download")),
+            format!("{PAGE_URL}/")
+        );
+        assert_eq!(
+            WallpaperFlare.page_url(&format!("{PAGE_URL}/download/")),
+            format!("{PAGE_URL}/")
+        );
+        assert_eq!(WallpaperFlare.page_url(PAGE_URL), PAGE_URL);
+    }
+
+    #[test]
+    fn test_scrape() {
+        let client = FakeClient::new(&[
+            (PAGE_URL, include_str!("fixtures/wallpaper_flare_page.html")),
+            (
+                &format!("{PAGE_URL}/download"),
+                include_str!("fixtures/wallpaper_flare_download.html"),
+            ),
+        ]);
+        assert_eq!(
+            WallpaperFlare.scrape(&client, PAGE_URL).unwrap(),
+            Scraped {
+                image_url: IMAGE_URL.to_string(),
+                metadata: Metadata {
+                    title: Some("mountain lake reflection".to_string()),
+                    author: None,
+                    licence: Some("Public domain".to_string()),
+                    tags: vec!["mountain".to_string(), "lake".to_string()],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_image_url_over_http() {
+        let path = "/mountain-lake-reflection-nature-landscape-wallpaper-pxzyg";
+        // The stub server can't know its own address before starting, so the link to the download
+        // page is made relative.
+        let base_url = serve(vec![
+            (
+                path,
+                include_str!("fixtures/wallpaper_flare_page.html")
+                    .replace("https://www.wallpaperflare.com", ""),
+            ),
+            (
+                "/mountain-lake-reflection-nature-landscape-wallpaper-pxzyg/download",
+                include_str!("fixtures/wallpaper_flare_download.html").to_string(),
+            ),
+        ]);
+        assert_eq!(
+            WallpaperFlare
+                .scrape(&ReqwestClient::new(), &format!("{base_url}{path}"))
+                .unwrap()
+                .image_url,
+            IMAGE_URL
+        );
+    }
+
+    #[test]
+    fn test_image_url_over_http_with_missing_page() {
+        let base_url = serve(vec![]);
+        assert!(WallpaperFlare
+            .scrape(&ReqwestClient::new(), &format!("{base_url}/missing"))
+            .is_err());
     }
-    println!("Downloading from {url}");
-
-    let first_source_code = reqwest::blocking::get(url)?.text()?;
-    let first_html = scraper::Html::parse_document(&first_source_code);
-    let selector_str = "a.link_btn.aq.mt20";
-    let err_ctx = DonErrorContext::new().with_ser("source code", &first_source_code);
-
-    let link_to_download_page = first_html
-        .select(
-            &scraper::Selector::parse(selector_str)
-                .map_err(|_| err_msg!("Failed to create the selector for '{selector_str}'"))?,
-        )
-        .next()
-        .ok_or_don_err("Couldn't find any tag 'a' with class 'link_btn aq mt20'")
-        .err_ctx(&err_ctx)?
-        .value()
-        .attr("href")
-        .ok_or_don_err("The tag was found but doesn't contain an attribute 'href'")
-        .err_ctx(&err_ctx)?;
-
-    let source_code = reqwest::blocking::get(link_to_download_page)?.text()?;
-    let html = scraper::Html::parse_document(&source_code);
-    let selector_str = "a.link_btn.aq.mt20";
-    let err_ctx = DonErrorContext::new().with_ser("source code", &source_code);
-
-    super::download_file(
-        html.select(
-            &scraper::Selector::parse(selector_str)
-                .map_err(|_| err_msg!("Failed to create the selector for '{selector_str}'"))?,
-        )
-        .next()
-        .ok_or_don_err("Couldn't find any tag 'img' with id 'show_img'")
-        .err_ctx(&err_ctx)?
-        .value()
-        .attr("src")
-        .ok_or_don_err("The tag was found but doesn't contain an attribute 'src'")
-        .err_ctx(&err_ctx)?,
-    )
 }
@@ -1,16 +1,29 @@
+mod catalogue;
 mod config;
+mod daemon;
 mod download;
 mod monitors;
+mod setters;
+mod state;
 mod wallpapers;
 
 pub use {
-    download::perform as download_wallpapers,
+    catalogue::{list as list_catalogue, show as show_catalogue_entry},
+    daemon::{control as control_daemon, run as run_daemon, Request as DaemonRequest},
+    download::{fetch_wallhaven, perform as download_wallpapers, WallhavenSearch},
     wallpapers::{
         change::{
-            every_n_min as change_wallpaper_every_n_minutes, once as change_wallpaper_once,
-            Mode as ChangeMode,
+            once as change_wallpaper_once, previous as previous_wallpapers, Mode as ChangeMode,
         },
-        sort::perform as sort_wallpapers,
+        dedup::{perform as dedup_wallpapers, Policy as DedupPolicy},
+        history::{current as current_wallpapers, print as print_history},
+        quarantine::report as quarantine_report,
+        ratings::{
+            ban as ban_wallpapers, favourite as favourite_wallpapers, rate as rate_wallpapers,
+            tag as tag_wallpapers,
+        },
+        sort::{perform as sort_wallpapers, undo as undo_sort},
+        watch::perform as watch_wallpapers,
     },
 };
 
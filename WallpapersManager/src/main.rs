@@ -1,11 +1,15 @@
 use wallpapers_manager::{
-    change_wallpaper_every_n_minutes, change_wallpaper_once, download_wallpapers, sort_wallpapers,
-    ChangeMode,
+    ban_wallpapers, change_wallpaper_once, control_daemon, current_wallpapers, dedup_wallpapers,
+    download_wallpapers, favourite_wallpapers, fetch_wallhaven, list_catalogue,
+    previous_wallpapers, print_history, quarantine_report, rate_wallpapers, run_daemon,
+    show_catalogue_entry, sort_wallpapers, tag_wallpapers, undo_sort, watch_wallpapers, ChangeMode,
+    DaemonRequest, DedupPolicy, WallhavenSearch,
 };
 
 use {
-    clap::{Parser, Subcommand},
+    clap::{Args, Parser, Subcommand},
     don_error::*,
+    std::path::PathBuf,
 };
 
 #[derive(Parser)]
@@ -17,19 +21,173 @@ struct WallCommand {
 #[derive(Subcommand)]
 enum Commands {
     Sort {
+        /// Also sort again the wallpapers already in a bucket
         #[arg(short, long, default_value = "false")]
         force_sort_all_wallpapers: bool,
+        /// Only print where the files would be moved
+        #[arg(short, long, default_value = "false")]
+        dry_run: bool,
+        /// Move back the files moved by the last sort
+        #[arg(
+            short,
+            long,
+            default_value = "false",
+            conflicts_with_all = ["force_sort_all_wallpapers", "dry_run"]
+        )]
+        undo: bool,
     },
+    /// Change the wallpapers now, through the daemon if one is running
+    #[command(alias = "next")]
     Change {
         #[arg(short, long, default_value = "proportionate-to-number-of-files")]
         mode: ChangeMode,
+        /// Only display wallpapers from this bucket
+        #[arg(short, long)]
+        bucket: Option<String>,
     },
     Download,
-    Cron {
+    /// Download the wallpapers a site finds for a search
+    Fetch {
+        #[command(subcommand)]
+        source: FetchSource,
+    },
+    /// Sort the files dropped into the wallpapers dir as soon as they're written
+    Watch,
+    /// Find wallpapers that look the same and remove all but one of them
+    Dedup {
+        #[arg(short, long, default_value = "keep-largest")]
+        policy: DedupPolicy,
+        /// Maximum number of different bits (out of 64) between the hashes of near-duplicates
+        #[arg(short, long, default_value = "10")]
+        threshold: u32,
+        /// Only print which files would be removed
+        #[arg(short, long, default_value = "false")]
+        dry_run: bool,
+    },
+    /// List the wallpapers quarantined by the last sort, and why
+    Quarantine,
+    /// Query what is known about the wallpapers of the library
+    Catalogue {
+        #[command(subcommand)]
+        query: CatalogueQuery,
+    },
+    /// Change the wallpapers every few minutes, and listen to the commands below
+    #[command(alias = "cron")]
+    Daemon {
         #[arg(short = 'd', long)]
         minutes: u64,
         #[arg(short, long, default_value = "proportionate-to-number-of-files")]
         mode: ChangeMode,
+        /// Only display wallpapers from this bucket
+        #[arg(short, long)]
+        bucket: Option<String>,
+        /// Also sort the files dropped into the wallpapers dir meanwhile
+        #[arg(short, long, default_value = "false")]
+        watch: bool,
+        /// Don't change the wallpapers until resumed
+        #[arg(short, long, default_value = "false")]
+        paused: bool,
+    },
+    /// Display the previous wallpapers again
+    Previous,
+    /// The wallpapers displayed on each monitor, and where they come from
+    Current,
+    /// The last wallpapers displayed
+    History {
+        /// Only on the monitor with this name
+        #[arg(short, long)]
+        monitor: Option<String>,
+        #[arg(short = 'n', long, default_value = "20")]
+        limit: usize,
+    },
+    /// Stop the daemon from changing the wallpapers
+    Pause,
+    Resume,
+    SetInterval {
+        minutes: u64,
+    },
+    SetMode {
+        mode: ChangeMode,
+    },
+    /// What the daemon is doing
+    Status,
+    /// Restart the daemon with the current config
+    ReloadConfig,
+    /// Tag wallpapers, for `catalogue list --tag`
+    Tag {
+        #[arg(short, long = "tag", required = true, value_delimiter = ',')]
+        tags: Vec<String>,
+        /// Remove the tags instead
+        #[arg(short, long, default_value = "false")]
+        remove: bool,
+        #[command(flatten)]
+        targets: Targets,
+    },
+    /// Rate wallpapers from 1 to 5 stars, 0 to forget the rating. The more stars, the more often
+    /// they're displayed
+    Rate {
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=5))]
+        stars: u8,
+        #[command(flatten)]
+        targets: Targets,
+    },
+    /// Never display wallpapers again
+    Ban {
+        /// Allow them again instead
+        #[arg(short, long, default_value = "false")]
+        remove: bool,
+        #[command(flatten)]
+        targets: Targets,
+    },
+    /// Display wallpapers twice as often
+    Fav {
+        /// Make them ordinary wallpapers again instead
+        #[arg(short, long, default_value = "false")]
+        remove: bool,
+        #[command(flatten)]
+        targets: Targets,
+    },
+}
+
+/// The wallpapers `tag`, `rate`, `ban` and `fav` apply to.
+#[derive(Args)]
+struct Targets {
+    /// The current wallpapers when empty
+    paths: Vec<PathBuf>,
+    /// Only the current wallpaper of the monitor with this name
+    #[arg(short, long, conflicts_with = "paths")]
+    monitor: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum FetchSource {
+    /// Through the API of wallhaven.cc, with the API key of the config if any
+    Wallhaven {
+        #[arg(short, long)]
+        query: Option<String>,
+        /// Aspect ratios like 16x9, comma separated
+        #[arg(short, long, value_delimiter = ',')]
+        ratio: Vec<String>,
+        /// Minimum resolution, like 2560x1440
+        #[arg(short = 'm', long)]
+        min_res: Option<String>,
+        #[arg(short = 'n', long, default_value = "24")]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum CatalogueQuery {
+    /// Everything known about a wallpaper: source, dimensions, display history...
+    Show { path: PathBuf },
+    /// All the wallpapers, optionally from a single site, aspect class or tag
+    List {
+        #[arg(short, long)]
+        site: Option<String>,
+        #[arg(short, long)]
+        aspect_class: Option<String>,
+        #[arg(short, long)]
+        tag: Option<String>,
     },
 }
 
@@ -38,10 +196,76 @@ fn main() -> DonResult<()> {
     match wall_command.command {
         Commands::Sort {
             force_sort_all_wallpapers,
-        } => sort_wallpapers(force_sort_all_wallpapers)?,
-        Commands::Change { mode } => change_wallpaper_once(&mode)?,
-        Commands::Cron { minutes, mode } => change_wallpaper_every_n_minutes(minutes, &mode)?,
+            dry_run,
+            undo,
+        } => match undo {
+            true => undo_sort()?,
+            false => sort_wallpapers(force_sort_all_wallpapers, dry_run)?,
+        },
+        Commands::Change { mode, bucket } => change_wallpaper_once(&mode, bucket.as_deref())?,
+        Commands::Daemon {
+            minutes,
+            mode,
+            bucket,
+            watch,
+            paused,
+        } => run_daemon(minutes, mode, bucket, watch, paused)?,
+        Commands::Previous => previous_wallpapers()?,
+        Commands::Current => current_wallpapers()?,
+        Commands::History { monitor, limit } => print_history(monitor.as_deref(), limit)?,
+        Commands::Pause => control_daemon(DaemonRequest::Pause)?,
+        Commands::Resume => control_daemon(DaemonRequest::Resume)?,
+        Commands::SetInterval { minutes } => {
+            control_daemon(DaemonRequest::SetInterval { minutes })?
+        }
+        Commands::SetMode { mode } => control_daemon(DaemonRequest::SetMode { mode })?,
+        Commands::Status => control_daemon(DaemonRequest::Status)?,
+        Commands::ReloadConfig => control_daemon(DaemonRequest::ReloadConfig)?,
         Commands::Download => download_wallpapers()?,
+        Commands::Fetch { source } => match source {
+            FetchSource::Wallhaven {
+                query,
+                ratio,
+                min_res,
+                limit,
+            } => fetch_wallhaven(
+                &WallhavenSearch {
+                    query,
+                    ratios: ratio,
+                    min_resolution: min_res,
+                },
+                limit,
+            )?,
+        },
+        Commands::Watch => watch_wallpapers()?,
+        Commands::Dedup {
+            policy,
+            threshold,
+            dry_run,
+        } => dedup_wallpapers(policy, threshold, dry_run)?,
+        Commands::Quarantine => quarantine_report()?,
+        Commands::Catalogue { query } => match query {
+            CatalogueQuery::Show { path } => show_catalogue_entry(&path)?,
+            CatalogueQuery::List {
+                site,
+                aspect_class,
+                tag,
+            } => list_catalogue(site.as_deref(), aspect_class.as_deref(), tag.as_deref())?,
+        },
+        Commands::Tag {
+            tags,
+            remove,
+            targets,
+        } => tag_wallpapers(&tags, remove, &targets.paths, targets.monitor.as_deref())?,
+        Commands::Rate { stars, targets } => {
+            rate_wallpapers(stars, &targets.paths, targets.monitor.as_deref())?
+        }
+        Commands::Ban { remove, targets } => {
+            ban_wallpapers(remove, &targets.paths, targets.monitor.as_deref())?
+        }
+        Commands::Fav { remove, targets } => {
+            favourite_wallpapers(remove, &targets.paths, targets.monitor.as_deref())?
+        }
     }
     Ok(())
 }
@@ -0,0 +1,262 @@
+#[cfg(feature = "tetra")]
+mod tetra;
+mod wayland;
+mod xrandr;
+
+use {
+    don_error::*,
+    std::{env::var_os, process::Command},
+};
+
+/// A way to find out which monitors are connected and where they are.
+pub(crate) trait MonitorProvider {
+    fn name(&self) -> &str;
+    fn layout(&self) -> DonResult<MonitorLayout>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Orientation {
+    Landscape,
+    Portrait,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Monitor {
+    pub(crate) name: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// Position of the top left corner on the desktop
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) orientation: Orientation,
+}
+
+/// A rectangle of the desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Bounds {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+/// The monitors, in the order the wallpaper backends expect one image per monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MonitorLayout {
+    pub(crate) monitors: Vec<Monitor>,
+}
+
+impl Monitor {
+    pub(crate) fn new(name: &str, width: u32, height: u32, x: i32, y: i32) -> Self {
+        Monitor {
+            name: name.to_string(),
+            width,
+            height,
+            x,
+            y,
+            orientation: match height > width {
+                true => Orientation::Portrait,
+                false => Orientation::Landscape,
+            },
+        }
+    }
+}
+
+impl MonitorLayout {
+    /// Asks the providers that make sense for the current session, the first answer wins.
+    pub(crate) fn detect() -> DonResult<Self> {
+        detect_with(&providers())
+    }
+
+    /// Groups (as indexes in `monitors`) of at least two monitors a wallpaper can span across :
+    /// monitors touching each other side by side, left to right. Their heights and vertical
+    /// offsets may differ, as long as they overlap. Every part of a row of such monitors is a
+    /// group, the longest first.
+    pub(crate) fn spanning_groups(&self) -> Vec<Vec<usize>> {
+        let mut groups = vec![];
+        for row in self.rows() {
+            for length in (2..=row.len()).rev() {
+                groups.extend(row.windows(length).map(<[usize]>::to_vec));
+            }
+        }
+        groups
+    }
+
+    /// Longest runs of monitors touching each other side by side.
+    fn rows(&self) -> Vec<Vec<usize>> {
+        let mut by_x = (0..self.monitors.len()).collect::<Vec<_>>();
+        by_x.sort_by_key(|&index| (self.monitors[index].x, self.monitors[index].y));
+
+        let mut groups: Vec<Vec<usize>> = vec![];
+        let mut group: Vec<usize> = vec![];
+        for index in by_x {
+            let monitor = &self.monitors[index];
+            let continues_group = group.last().is_some_and(|&previous| {
+                let previous = &self.monitors[previous];
+                previous.x + previous.width as i32 == monitor.x
+                    && previous.y < monitor.y + monitor.height as i32
+                    && monitor.y < previous.y + previous.height as i32
+            });
+            if !continues_group {
+                if group.len() > 1 {
+                    groups.push(std::mem::take(&mut group));
+                }
+                group.clear();
+            }
+            group.push(index);
+        }
+        if group.len() > 1 {
+            groups.push(group);
+        }
+        groups
+    }
+
+    /// Smallest rectangle of the desktop containing the monitors of `group`.
+    pub(crate) fn bounds(&self, group: &[usize]) -> Bounds {
+        let monitors = group.iter().map(|&index| &self.monitors[index]);
+        let x = monitors
+            .clone()
+            .map(|monitor| monitor.x)
+            .min()
+            .unwrap_or_default();
+        let y = monitors
+            .clone()
+            .map(|monitor| monitor.y)
+            .min()
+            .unwrap_or_default();
+        let right = monitors
+            .clone()
+            .map(|monitor| monitor.x + monitor.width as i32)
+            .max()
+            .unwrap_or_default();
+        let bottom = monitors
+            .map(|monitor| monitor.y + monitor.height as i32)
+            .max()
+            .unwrap_or_default();
+        Bounds {
+            x,
+            y,
+            width: (right - x) as u32,
+            height: (bottom - y) as u32,
+        }
+    }
+
+    /// Indexes of all the monitors.
+    pub(crate) fn all(&self) -> Vec<usize> {
+        (0..self.monitors.len()).collect()
+    }
+}
+
+fn providers() -> Vec<Box<dyn MonitorProvider>> {
+    let mut providers: Vec<Box<dyn MonitorProvider>> = vec![];
+    if var_os("WAYLAND_DISPLAY").is_some() {
+        if var_os("SWAYSOCK").is_some() {
+            providers.push(Box::new(wayland::Sway));
+        }
+        providers.push(Box::new(wayland::WlrRandr));
+    }
+    if var_os("DISPLAY").is_some() {
+        providers.push(Box::new(xrandr::Xrandr));
+    }
+    #[cfg(feature = "tetra")]
+    providers.push(Box::new(tetra::Tetra));
+    providers
+}
+
+fn detect_with(providers: &[Box<dyn MonitorProvider>]) -> DonResult<MonitorLayout> {
+    let mut failures = vec![];
+    for provider in providers {
+        match provider.layout() {
+            Ok(layout) => return Ok(layout),
+            Err(err) => failures.push(format!("{} : {err}", provider.name())),
+        }
+    }
+    bail!("Couldn't detect the monitors {failures:#?}")
+}
+
+/// Output of a command, failing if it doesn't exist or doesn't succeed.
+fn run(program: &str, args: &[&str]) -> DonResult<String> {
+    let output = Command::new(program).args(args).output()?;
+    if !output.status.success() {
+        bail!(
+            "Command didn't execute successfully : {:#?}",
+            String::from_utf8(output.stderr)?
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use super::*;
+
+    /// Returns the same layout every time, or fails if it has none.
+    pub(crate) struct FakeProvider(pub(crate) Option<MonitorLayout>);
+
+    impl MonitorProvider for FakeProvider {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        fn layout(&self) -> DonResult<MonitorLayout> {
+            self.0.clone().ok_or_don_err("No monitor")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{test_helpers::FakeProvider, *};
+
+    #[test]
+    fn test_spanning_groups() {
+        let layout = MonitorLayout {
+            monitors: vec![
+                Monitor::new("DP-2", 1920, 1080, 1920, 0),
+                Monitor::new("DP-1", 1920, 1080, 0, 0),
+                // Not touching DP-2
+                Monitor::new("HDMI-1", 1920, 1080, 4000, 0),
+                Monitor::new("HDMI-2", 2560, 1080, 5920, 0),
+                Monitor::new("DP-3", 1080, 1920, 8480, 0),
+            ],
+        };
+        assert_eq!(
+            layout.spanning_groups(),
+            vec![vec![1, 0], vec![2, 3, 4], vec![2, 3], vec![3, 4]]
+        );
+        assert_eq!(
+            layout.bounds(&[2, 3, 4]),
+            Bounds {
+                x: 4000,
+                y: 0,
+                width: 5560,
+                height: 1920
+            }
+        );
+        assert_eq!(layout.monitors[4].orientation, Orientation::Portrait);
+    }
+
+    #[test]
+    fn test_detect_falls_back_to_next_provider() {
+        let layout = MonitorLayout {
+            monitors: vec![Monitor::new("DP-1", 3840, 2160, 0, 0)],
+        };
+        let providers: Vec<Box<dyn MonitorProvider>> = vec![
+            Box::new(FakeProvider(None)),
+            Box::new(FakeProvider(Some(layout.clone()))),
+        ];
+        assert_eq!(detect_with(&providers).unwrap(), layout);
+        assert!(detect_with(&providers[..1]).is_err());
+    }
+
+    #[test]
+    fn test_no_spanning_group_without_vertical_overlap() {
+        let layout = MonitorLayout {
+            monitors: vec![
+                Monitor::new("eDP-1", 1920, 1200, 0, 1080),
+                Monitor::new("HDMI-1", 1920, 1080, 1920, 0),
+            ],
+        };
+        assert!(layout.spanning_groups().is_empty());
+    }
+}
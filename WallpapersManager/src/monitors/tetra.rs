@@ -0,0 +1,44 @@
+use {
+    super::{Monitor, MonitorLayout, MonitorProvider},
+    don_error::*,
+    tetra::{
+        window::{get_monitor_count, get_monitor_name, get_monitor_size},
+        ContextBuilder,
+    },
+};
+
+/// Monitors as seen by SDL, through a hidden tetra window. Needs a graphical session.
+pub(crate) struct Tetra;
+
+impl MonitorProvider for Tetra {
+    fn name(&self) -> &str {
+        "tetra"
+    }
+
+    fn layout(&self) -> DonResult<MonitorLayout> {
+        let context = ContextBuilder::new("Get monitors", 1, 1)
+            .visible(false)
+            .build()?;
+        let nb_monitors = get_monitor_count(&context)?;
+        if nb_monitors == 0 {
+            bail!("No screen availables");
+        }
+        // Tetra doesn't tell where the monitors are, so they are assumed to be side by side
+        let mut monitors: Vec<Monitor> = vec![];
+        for index in 0..nb_monitors {
+            let (width, height) = get_monitor_size(&context, index)?;
+            let x = monitors
+                .last()
+                .map(|monitor| monitor.x + monitor.width as i32)
+                .unwrap_or_default();
+            monitors.push(Monitor::new(
+                &get_monitor_name(&context, index)?,
+                width as u32,
+                height as u32,
+                x,
+                0,
+            ));
+        }
+        Ok(MonitorLayout { monitors })
+    }
+}
@@ -0,0 +1,271 @@
+use {
+    super::{run, Monitor, MonitorLayout, MonitorProvider},
+    don_error::*,
+    serde::Deserialize,
+};
+
+/// Outputs of the sway compositor, as listed by `swaymsg -t get_outputs`.
+pub(crate) struct Sway;
+
+/// Outputs of wlroots based compositors, as listed by `wlr-randr --json`.
+pub(crate) struct WlrRandr;
+
+#[derive(Deserialize)]
+struct SwayOutput {
+    name: String,
+    active: bool,
+    #[serde(default = "default_scale")]
+    scale: f64,
+    /// In logical pixels, already rotated
+    rect: Rect,
+}
+
+#[derive(Deserialize)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Deserialize)]
+struct WlrRandrOutput {
+    name: String,
+    enabled: bool,
+    modes: Vec<Mode>,
+    /// In logical pixels
+    position: Position,
+    transform: String,
+    scale: f64,
+}
+
+#[derive(Deserialize)]
+struct Mode {
+    width: u32,
+    height: u32,
+    current: bool,
+}
+
+#[derive(Deserialize)]
+struct Position {
+    x: i32,
+    y: i32,
+}
+
+fn default_scale() -> f64 {
+    1.
+}
+
+impl MonitorProvider for Sway {
+    fn name(&self) -> &str {
+        "swaymsg"
+    }
+
+    fn layout(&self) -> DonResult<MonitorLayout> {
+        parse_sway(&run("swaymsg", &["-t", "get_outputs", "--raw"])?)
+    }
+}
+
+impl MonitorProvider for WlrRandr {
+    fn name(&self) -> &str {
+        "wlr-randr"
+    }
+
+    fn layout(&self) -> DonResult<MonitorLayout> {
+        parse_wlr_randr(&run("wlr-randr", &["--json"])?)
+    }
+}
+
+/// An output with its position in logical pixels and its size in physical ones.
+struct Output {
+    name: String,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    scale: f64,
+}
+
+/// Where an output lies along one axis.
+struct Span {
+    /// In logical pixels
+    start: i32,
+    /// In logical pixels
+    logical_length: i32,
+    physical_length: u32,
+    scale: f64,
+}
+
+impl Span {
+    fn end(&self) -> i32 {
+        self.start + self.logical_length
+    }
+}
+
+fn scaled(value: f64, scale: f64) -> f64 {
+    (value * scale).round()
+}
+
+/// Physical positions of the `spans` along their axis. Positions are given in logical pixels,
+/// whose size depends on the scale of each output : an output starts where the ones before it end
+/// in physical pixels, so that they still touch each other whatever their scales.
+fn physical_positions(spans: &[Span]) -> Vec<i32> {
+    let origin = spans
+        .iter()
+        .map(|span| span.start)
+        .min()
+        .unwrap_or_default();
+    let mut order = (0..spans.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&index| spans[index].start);
+    let mut positions = vec![0; spans.len()];
+    for (nb_done, &index) in order.iter().enumerate() {
+        let span = &spans[index];
+        let gap = |before_end: i32| scaled((span.start - before_end) as f64, span.scale) as i32;
+        positions[index] = order[..nb_done]
+            .iter()
+            .filter(|&&before| spans[before].end() <= span.start)
+            .map(|&before| {
+                positions[before] + spans[before].physical_length as i32 + gap(spans[before].end())
+            })
+            .max()
+            .unwrap_or_else(|| gap(origin));
+    }
+    positions
+}
+
+fn parse_sway(json: &str) -> DonResult<MonitorLayout> {
+    let outputs: Vec<SwayOutput> = serde_json::from_str(json)?;
+    layout(
+        outputs
+            .into_iter()
+            .filter(|output| output.active)
+            .map(|output| {
+                let (rect, scale) = (output.rect, output.scale);
+                Output {
+                    name: output.name,
+                    x: rect.x,
+                    y: rect.y,
+                    width: scaled(rect.width as f64, scale) as u32,
+                    height: scaled(rect.height as f64, scale) as u32,
+                    scale,
+                }
+            })
+            .collect(),
+    )
+}
+
+fn parse_wlr_randr(json: &str) -> DonResult<MonitorLayout> {
+    let outputs: Vec<WlrRandrOutput> = serde_json::from_str(json)?;
+    let mut enabled = vec![];
+    for output in outputs.into_iter().filter(|output| output.enabled) {
+        let mode = output
+            .modes
+            .iter()
+            .find(|mode| mode.current)
+            .ok_or_don_err(format!("{} has no current mode", output.name))?;
+        let rotated = matches!(
+            output.transform.as_str(),
+            "90" | "270" | "flipped-90" | "flipped-270"
+        );
+        let (width, height) = match rotated {
+            true => (mode.height, mode.width),
+            false => (mode.width, mode.height),
+        };
+        enabled.push(Output {
+            name: output.name,
+            x: output.position.x,
+            y: output.position.y,
+            width,
+            height,
+            scale: output.scale,
+        });
+    }
+    layout(enabled)
+}
+
+fn layout(outputs: Vec<Output>) -> DonResult<MonitorLayout> {
+    if outputs.is_empty() {
+        bail!("No active output");
+    }
+    let span = |start: i32, length: u32, scale: f64| Span {
+        start,
+        logical_length: scaled(length as f64, 1. / scale) as i32,
+        physical_length: length,
+        scale,
+    };
+    let xs = physical_positions(
+        &outputs
+            .iter()
+            .map(|output| span(output.x, output.width, output.scale))
+            .collect::<Vec<_>>(),
+    );
+    let ys = physical_positions(
+        &outputs
+            .iter()
+            .map(|output| span(output.y, output.height, output.scale))
+            .collect::<Vec<_>>(),
+    );
+    Ok(MonitorLayout {
+        monitors: outputs
+            .iter()
+            .zip(xs.into_iter().zip(ys))
+            .map(|(output, (x, y))| Monitor::new(&output.name, output.width, output.height, x, y))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::monitors::Orientation;
+
+    #[test]
+    fn test_parse_sway() {
+        let layout = parse_sway(include_str!("fixtures/sway_outputs.json")).unwrap();
+        assert_eq!(
+            layout.monitors,
+            vec![
+                Monitor::new("eDP-1", 2256, 1504, 0, 0),
+                Monitor::new("DP-3", 2160, 3840, 2256, 0),
+            ]
+        );
+        assert_eq!(layout.monitors[1].orientation, Orientation::Portrait);
+    }
+
+    #[test]
+    fn test_parse_wlr_randr() {
+        let layout = parse_wlr_randr(include_str!("fixtures/wlr_randr.json")).unwrap();
+        assert_eq!(
+            layout.monitors,
+            vec![
+                Monitor::new("DP-1", 1920, 1080, 0, 0),
+                Monitor::new("DP-2", 1920, 1080, 1920, 0),
+            ]
+        );
+        assert_eq!(layout.spanning_groups(), vec![vec![0, 1]]);
+    }
+
+    #[test]
+    fn test_mixed_scales() {
+        // A 4K monitor at 1.5 on the right of a laptop at 2
+        let layout = parse_sway(include_str!("fixtures/sway_outputs_mixed_scales.json")).unwrap();
+        assert_eq!(
+            layout.monitors,
+            vec![
+                Monitor::new("eDP-1", 2560, 1600, 0, 0),
+                Monitor::new("DP-1", 3840, 2160, 2560, 0),
+            ]
+        );
+        // A 1080p monitor at 1 on the right of a 4K one at 2, and another one below them
+        let layout = parse_wlr_randr(include_str!("fixtures/wlr_randr_mixed_scales.json")).unwrap();
+        assert_eq!(
+            layout.monitors,
+            vec![
+                Monitor::new("DP-1", 3840, 2160, 0, 0),
+                Monitor::new("DP-2", 1920, 1080, 3840, 0),
+                Monitor::new("HDMI-A-1", 1920, 1080, 0, 2160),
+            ]
+        );
+    }
+}
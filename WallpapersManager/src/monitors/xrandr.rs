@@ -0,0 +1,90 @@
+use {
+    super::{run, Monitor, MonitorLayout, MonitorProvider},
+    don_error::*,
+};
+
+/// X11 monitors, as listed by `xrandr --query`.
+pub(crate) struct Xrandr;
+
+impl MonitorProvider for Xrandr {
+    fn name(&self) -> &str {
+        "xrandr"
+    }
+
+    fn layout(&self) -> DonResult<MonitorLayout> {
+        parse(&run("xrandr", &["--query"])?)
+    }
+}
+
+/// Keeps the connected and active outputs, i.e. the ones with a geometry such as
+/// `DP-1 connected primary 2560x1080+1920+420 (normal left...`
+fn parse(output: &str) -> DonResult<MonitorLayout> {
+    let mut monitors = vec![];
+    for line in output.lines() {
+        let mut words = line.split_whitespace();
+        let (Some(name), Some("connected")) = (words.next(), words.next()) else {
+            continue;
+        };
+        let mut primary = false;
+        for word in words {
+            if word == "primary" {
+                primary = true;
+            } else if let Some(geometry) = parse_geometry(word) {
+                let (width, height, x, y) = geometry;
+                let monitor = Monitor::new(name, width, height, x, y);
+                // Xinerama, which feh relies on, numbers the primary monitor first
+                match primary {
+                    true => monitors.insert(0, monitor),
+                    false => monitors.push(monitor),
+                }
+                break;
+            }
+        }
+    }
+    if monitors.is_empty() {
+        bail!("No active monitor in the output of xrandr");
+    }
+    Ok(MonitorLayout { monitors })
+}
+
+/// `1920x1080+0+420` => (1920, 1080, 0, 420)
+fn parse_geometry(word: &str) -> Option<(u32, u32, i32, i32)> {
+    let (width, rest) = word.split_once('x')?;
+    let mut rest = rest.split('+');
+    Some((
+        width.parse().ok()?,
+        rest.next()?.parse().ok()?,
+        rest.next()?.parse().ok()?,
+        rest.next()?.parse().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::monitors::Orientation;
+
+    #[test]
+    fn test_parse() {
+        let layout = parse(include_str!("fixtures/xrandr.txt")).unwrap();
+        assert_eq!(
+            layout
+                .monitors
+                .iter()
+                .map(|monitor| (monitor.name.as_str(), monitor.x, monitor.y))
+                .collect::<Vec<_>>(),
+            vec![("DP-1", 1920, 420), ("HDMI-1", 0, 420), ("DP-2", 4480, 0)]
+        );
+        assert_eq!(layout.monitors[2].orientation, Orientation::Portrait);
+        assert_eq!(
+            layout.spanning_groups(),
+            vec![vec![1, 0, 2], vec![1, 0], vec![0, 2]]
+        );
+    }
+
+    #[test]
+    fn test_parse_without_active_monitor() {
+        assert!(parse("Screen 0: minimum 320 x 200, current 0 x 0\nDP-1 disconnected\n").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use {
+    super::{path_arg, single_image, Invocation, WallpaperSetter, Wallpapers},
+    crate::monitors::MonitorLayout,
+    don_error::*,
+};
+
+/// GNOME displays the same image on every monitor, or spans it across all of them. A different
+/// image per monitor has to be composed into a spanning one.
+pub(crate) struct Gnome;
+
+/// KDE Plasma, through the tool it ships with, displays the same image on every monitor.
+pub(crate) struct Plasma;
+
+const GNOME_BACKGROUND_SCHEMA: &str = "org.gnome.desktop.background";
+
+impl WallpaperSetter for Gnome {
+    fn name(&self) -> &str {
+        "gnome"
+    }
+
+    fn spans(&self) -> bool {
+        true
+    }
+
+    fn per_monitor(&self) -> bool {
+        false
+    }
+
+    fn commands(
+        &self,
+        layout: &MonitorLayout,
+        wallpapers: &Wallpapers,
+    ) -> DonResult<Vec<Invocation>> {
+        let uri = format!(
+            "file://{}",
+            path_arg(single_image(self, layout, wallpapers)?)
+        );
+        let options = match wallpapers {
+            Wallpapers::Spanning(_) => "spanned",
+            Wallpapers::PerMonitor(_) => "zoom",
+        };
+        let gsettings = |key: &str, value: &str| Invocation {
+            program: "gsettings",
+            args: vec![
+                "set".to_string(),
+                GNOME_BACKGROUND_SCHEMA.to_string(),
+                key.to_string(),
+                value.to_string(),
+            ],
+            background: false,
+        };
+        Ok(vec![
+            gsettings("picture-options", options),
+            gsettings("picture-uri", &uri),
+            // Used instead of picture-uri with the dark style
+            gsettings("picture-uri-dark", &uri),
+        ])
+    }
+}
+
+impl WallpaperSetter for Plasma {
+    fn name(&self) -> &str {
+        "plasma"
+    }
+
+    fn per_monitor(&self) -> bool {
+        false
+    }
+
+    fn commands(
+        &self,
+        layout: &MonitorLayout,
+        wallpapers: &Wallpapers,
+    ) -> DonResult<Vec<Invocation>> {
+        Ok(vec![Invocation {
+            program: "plasma-apply-wallpaperimage",
+            args: vec![path_arg(single_image(self, layout, wallpapers)?)],
+            background: false,
+        }])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use {crate::monitors::Monitor, std::path::PathBuf};
+
+    #[test]
+    fn test_gnome_spans() {
+        let layout = MonitorLayout {
+            monitors: vec![
+                Monitor::new("DP-1", 1920, 1080, 0, 0),
+                Monitor::new("DP-2", 1920, 1080, 1920, 0),
+            ],
+        };
+        let commands = Gnome
+            .commands(
+                &layout,
+                &Wallpapers::Spanning(PathBuf::from("/w/dual/city.jpg")),
+            )
+            .unwrap();
+        assert_eq!(commands[0].args[3], "spanned");
+        assert_eq!(
+            commands[2].args[2..],
+            ["picture-uri-dark", "file:///w/dual/city.jpg"]
+        );
+        // Only one image for all the monitors
+        assert!(Plasma
+            .commands(
+                &layout,
+                &Wallpapers::PerMonitor(vec![PathBuf::from("a.jpg"), PathBuf::from("b.jpg")])
+            )
+            .is_err());
+        assert!(Plasma
+            .commands(
+                &layout,
+                &Wallpapers::PerMonitor(vec![PathBuf::from("a.jpg"), PathBuf::from("a.jpg")])
+            )
+            .is_ok());
+    }
+}
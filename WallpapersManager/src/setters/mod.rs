@@ -0,0 +1,225 @@
+mod desktop;
+mod wayland;
+mod x11;
+
+use crate::monitors::MonitorLayout;
+
+use {
+    don_error::*,
+    serde::Deserialize,
+    std::{
+        env::var,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    },
+};
+
+/// The images to display.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Wallpapers {
+    /// One image per monitor, in the order of the `MonitorLayout`
+    PerMonitor(Vec<PathBuf>),
+    /// One image across all the monitors, only for setters that can span by themselves
+    Spanning(PathBuf),
+}
+
+/// A program to run to display the wallpapers.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Invocation {
+    pub(crate) program: &'static str,
+    pub(crate) args: Vec<String>,
+    /// Keeps running to display the wallpapers, so it isn't waited for
+    pub(crate) background: bool,
+}
+
+/// A way to display wallpapers, depending on the desktop environment.
+pub(crate) trait WallpaperSetter {
+    fn name(&self) -> &str;
+
+    /// Whether `Wallpapers::Spanning` is supported.
+    fn spans(&self) -> bool {
+        false
+    }
+
+    /// Whether each monitor can display a different image.
+    fn per_monitor(&self) -> bool {
+        true
+    }
+
+    fn commands(
+        &self,
+        layout: &MonitorLayout,
+        wallpapers: &Wallpapers,
+    ) -> DonResult<Vec<Invocation>>;
+
+    fn set(&self, layout: &MonitorLayout, wallpapers: &Wallpapers) -> DonResult<()> {
+        run_all(&self.commands(layout, wallpapers)?)
+    }
+}
+
+/// Setter chosen in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SetterKind {
+    Feh,
+    Xwallpaper,
+    Swaybg,
+    Swww,
+    Gnome,
+    Plasma,
+}
+
+/// The setter of the config, or the one that suits the current session.
+pub(crate) fn setter(kind: Option<SetterKind>) -> Box<dyn WallpaperSetter> {
+    let kind = kind.unwrap_or_else(|| {
+        detect_kind(
+            &var("XDG_CURRENT_DESKTOP").unwrap_or_default(),
+            var("WAYLAND_DISPLAY").is_ok(),
+            var("SWAYSOCK").is_ok(),
+        )
+    });
+    match kind {
+        SetterKind::Feh => Box::new(x11::Feh),
+        SetterKind::Xwallpaper => Box::new(x11::Xwallpaper),
+        SetterKind::Swaybg => Box::new(wayland::Swaybg),
+        SetterKind::Swww => Box::new(wayland::Swww),
+        SetterKind::Gnome => Box::new(desktop::Gnome),
+        SetterKind::Plasma => Box::new(desktop::Plasma),
+    }
+}
+
+fn detect_kind(current_desktop: &str, wayland: bool, sway: bool) -> SetterKind {
+    // Desktops draw their own background above anything set by other tools
+    if current_desktop.split(':').any(|desktop| desktop == "GNOME") {
+        SetterKind::Gnome
+    } else if current_desktop.split(':').any(|desktop| desktop == "KDE") {
+        SetterKind::Plasma
+    } else if sway {
+        SetterKind::Swaybg
+    } else if wayland {
+        SetterKind::Swww
+    } else {
+        SetterKind::Feh
+    }
+}
+
+/// The image to display when the setter can only display one for all the monitors.
+fn single_image<'w>(
+    setter: &dyn WallpaperSetter,
+    layout: &MonitorLayout,
+    wallpapers: &'w Wallpapers,
+) -> DonResult<&'w Path> {
+    match wallpapers {
+        Wallpapers::Spanning(image) => Ok(image),
+        // The same image on every monitor
+        Wallpapers::PerMonitor(images)
+            if images.len() == layout.monitors.len()
+                && images
+                    .first()
+                    .is_some_and(|first| images.iter().all(|image| image == first)) =>
+        {
+            Ok(&images[0])
+        }
+        Wallpapers::PerMonitor(_) => bail!(
+            "{} can't display a different wallpaper on each monitor",
+            setter.name()
+        ),
+    }
+}
+
+/// One image per monitor, along with the monitor's name.
+fn per_monitor<'w>(
+    setter: &dyn WallpaperSetter,
+    layout: &'w MonitorLayout,
+    wallpapers: &'w Wallpapers,
+) -> DonResult<Vec<(&'w str, &'w Path)>> {
+    match wallpapers {
+        Wallpapers::PerMonitor(images) if images.len() == layout.monitors.len() => Ok(layout
+            .monitors
+            .iter()
+            .zip(images)
+            .map(|(monitor, image)| (monitor.name.as_str(), image.as_path()))
+            .collect()),
+        Wallpapers::PerMonitor(images) => bail!(
+            "{} images for {} monitors",
+            images.len(),
+            layout.monitors.len()
+        ),
+        Wallpapers::Spanning(_) => bail!("{} can't span an image by itself", setter.name()),
+    }
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+fn run_all(invocations: &[Invocation]) -> DonResult<()> {
+    for invocation in invocations {
+        let mut command = Command::new(invocation.program);
+        command.args(&invocation.args).stdout(Stdio::null());
+        if invocation.background {
+            command.stderr(Stdio::null()).spawn()?;
+            continue;
+        }
+        let output = command.output()?;
+        if !output.status.success() {
+            bail!(
+                "Command didn't execute successfully : {:#?}",
+                String::from_utf8(output.stderr)?
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    /// Records what it was asked to display instead of displaying it.
+    #[derive(Default)]
+    pub(crate) struct RecordingSetter {
+        pub(crate) spans: bool,
+        pub(crate) single_image: bool,
+        pub(crate) displayed: RefCell<Vec<Wallpapers>>,
+    }
+
+    impl WallpaperSetter for RecordingSetter {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn spans(&self) -> bool {
+            self.spans
+        }
+
+        fn per_monitor(&self) -> bool {
+            !self.single_image
+        }
+
+        fn commands(&self, _: &MonitorLayout, _: &Wallpapers) -> DonResult<Vec<Invocation>> {
+            Ok(vec![])
+        }
+
+        fn set(&self, _: &MonitorLayout, wallpapers: &Wallpapers) -> DonResult<()> {
+            self.displayed.borrow_mut().push(wallpapers.clone());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_detect_kind() {
+        assert_eq!(detect_kind("ubuntu:GNOME", true, false), SetterKind::Gnome);
+        assert_eq!(detect_kind("KDE", false, false), SetterKind::Plasma);
+        assert_eq!(detect_kind("sway", true, true), SetterKind::Swaybg);
+        assert_eq!(detect_kind("Hyprland", true, false), SetterKind::Swww);
+        assert_eq!(detect_kind("i3", false, false), SetterKind::Feh);
+    }
+}
@@ -0,0 +1,106 @@
+use {
+    super::{path_arg, per_monitor, run_all, Invocation, WallpaperSetter, Wallpapers},
+    crate::monitors::MonitorLayout,
+    don_error::*,
+    std::process::Command,
+};
+
+/// Keeps running to display the wallpapers, the previous one has to be stopped.
+pub(crate) struct Swaybg;
+
+/// Asks the `swww-daemon`, which has to be running already.
+pub(crate) struct Swww;
+
+impl WallpaperSetter for Swaybg {
+    fn name(&self) -> &str {
+        "swaybg"
+    }
+
+    fn commands(
+        &self,
+        layout: &MonitorLayout,
+        wallpapers: &Wallpapers,
+    ) -> DonResult<Vec<Invocation>> {
+        let mut args = vec![];
+        for (monitor, image) in per_monitor(self, layout, wallpapers)? {
+            args.extend([
+                "--output".to_string(),
+                monitor.to_string(),
+                "--image".to_string(),
+                path_arg(image),
+                "--mode".to_string(),
+                "fill".to_string(),
+            ]);
+        }
+        Ok(vec![Invocation {
+            program: "swaybg",
+            args,
+            background: true,
+        }])
+    }
+
+    fn set(&self, layout: &MonitorLayout, wallpapers: &Wallpapers) -> DonResult<()> {
+        let commands = self.commands(layout, wallpapers)?;
+        // Fails when there was no swaybg running
+        let _ = Command::new("pkill").args(["-x", "swaybg"]).status();
+        run_all(&commands)
+    }
+}
+
+impl WallpaperSetter for Swww {
+    fn name(&self) -> &str {
+        "swww"
+    }
+
+    fn commands(
+        &self,
+        layout: &MonitorLayout,
+        wallpapers: &Wallpapers,
+    ) -> DonResult<Vec<Invocation>> {
+        Ok(per_monitor(self, layout, wallpapers)?
+            .into_iter()
+            .map(|(monitor, image)| Invocation {
+                program: "swww",
+                args: vec![
+                    "img".to_string(),
+                    "--outputs".to_string(),
+                    monitor.to_string(),
+                    path_arg(image),
+                ],
+                background: false,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use {crate::monitors::Monitor, std::path::PathBuf};
+
+    #[test]
+    fn test_swaybg_one_process_for_all_outputs() {
+        let layout = MonitorLayout {
+            monitors: vec![
+                Monitor::new("eDP-1", 2256, 1504, 0, 0),
+                Monitor::new("DP-3", 2160, 3840, 2256, 0),
+            ],
+        };
+        let commands = Swaybg
+            .commands(
+                &layout,
+                &Wallpapers::PerMonitor(vec![PathBuf::from("a.jpg"), PathBuf::from("b.jpg")]),
+            )
+            .unwrap();
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].background);
+        assert_eq!(
+            commands[0].args[6..],
+            ["--output", "DP-3", "--image", "b.jpg", "--mode", "fill"]
+        );
+        assert!(Swww
+            .commands(&layout, &Wallpapers::Spanning(PathBuf::from("a.jpg")))
+            .is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use {
+    super::{path_arg, per_monitor, Invocation, WallpaperSetter, Wallpapers},
+    crate::monitors::MonitorLayout,
+    don_error::*,
+};
+
+pub(crate) struct Feh;
+
+pub(crate) struct Xwallpaper;
+
+impl WallpaperSetter for Feh {
+    fn name(&self) -> &str {
+        "feh"
+    }
+
+    fn spans(&self) -> bool {
+        true
+    }
+
+    fn commands(
+        &self,
+        _layout: &MonitorLayout,
+        wallpapers: &Wallpapers,
+    ) -> DonResult<Vec<Invocation>> {
+        let mut args = vec!["--bg-max".to_string(), "--no-fehbg".to_string()];
+        match wallpapers {
+            // feh gives the images to the monitors in the order of Xinerama
+            Wallpapers::PerMonitor(images) => {
+                args.extend(images.iter().map(|image| path_arg(image)))
+            }
+            Wallpapers::Spanning(image) => {
+                args.push("--no-xinerama".to_string());
+                args.push(path_arg(image));
+            }
+        }
+        Ok(vec![Invocation {
+            program: "feh",
+            args,
+            background: false,
+        }])
+    }
+}
+
+impl WallpaperSetter for Xwallpaper {
+    fn name(&self) -> &str {
+        "xwallpaper"
+    }
+
+    fn commands(
+        &self,
+        layout: &MonitorLayout,
+        wallpapers: &Wallpapers,
+    ) -> DonResult<Vec<Invocation>> {
+        let mut args = vec![];
+        for (monitor, image) in per_monitor(self, layout, wallpapers)? {
+            args.extend([
+                "--output".to_string(),
+                monitor.to_string(),
+                "--zoom".to_string(),
+                path_arg(image),
+            ]);
+        }
+        Ok(vec![Invocation {
+            program: "xwallpaper",
+            args,
+            background: false,
+        }])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use {crate::monitors::Monitor, std::path::PathBuf};
+
+    fn dual_layout() -> MonitorLayout {
+        MonitorLayout {
+            monitors: vec![
+                Monitor::new("DP-1", 1920, 1080, 0, 0),
+                Monitor::new("HDMI-1", 1920, 1080, 1920, 0),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_feh_spans() {
+        let commands = Feh
+            .commands(
+                &dual_layout(),
+                &Wallpapers::Spanning(PathBuf::from("/wallpapers/dual/city.jpg")),
+            )
+            .unwrap();
+        assert_eq!(
+            commands[0].args,
+            [
+                "--bg-max",
+                "--no-fehbg",
+                "--no-xinerama",
+                "/wallpapers/dual/city.jpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_xwallpaper_per_monitor() {
+        let layout = dual_layout();
+        let commands = Xwallpaper
+            .commands(
+                &layout,
+                &Wallpapers::PerMonitor(vec![PathBuf::from("a.jpg"), PathBuf::from("b.jpg")]),
+            )
+            .unwrap();
+        assert_eq!(
+            commands[0].args,
+            ["--output", "DP-1", "--zoom", "a.jpg", "--output", "HDMI-1", "--zoom", "b.jpg"]
+        );
+        assert!(Xwallpaper
+            .commands(
+                &layout,
+                &Wallpapers::PerMonitor(vec![PathBuf::from("a.jpg")])
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use {
+    don_error::*,
+    serde::{de::DeserializeOwned, Serialize},
+    std::{
+        env::var,
+        fs::{create_dir_all, read_to_string, rename, write, File},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Directory where the manager keeps its own files (lock, caches, history...).
+pub(crate) fn state_dir() -> DonResult<PathBuf> {
+    let state_dir = PathBuf::from(format!("{}/.wallpapers-mgr", var("HOME")?));
+    if !state_dir.exists() {
+        create_dir_all(&state_dir)?;
+    }
+    Ok(state_dir)
+}
+
+/// Waits until no other process, nor thread, holds the lock file `file_name` of the state dir,
+/// then holds it until the returned file is dropped.
+pub(crate) fn lock(file_name: &str) -> DonResult<File> {
+    lock_file(&state_dir()?.join(file_name))
+}
+
+fn lock_file(path: &Path) -> DonResult<File> {
+    let file = File::create(path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Reads `file_name` from the state dir, or returns the default value if it doesn't exist yet.
+pub(crate) fn load_json<T: DeserializeOwned + Default>(file_name: &str) -> DonResult<T> {
+    let path = state_dir()?.join(file_name);
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    serde_json::from_str(&read_to_string(&path)?).err_ctx_val("path", path.to_string_lossy())
+}
+
+/// Writes `value` to `file_name` in the state dir. The file is replaced atomically so that an
+/// interrupted write doesn't lose the previous state.
+pub(crate) fn save_json<T: Serialize>(file_name: &str, value: &T) -> DonResult<()> {
+    let path = state_dir()?.join(file_name);
+    let tmp_path = path.with_extension("tmp");
+    write(&tmp_path, serde_json::to_string(value)?)?;
+    rename(tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::fs::TryLockError};
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let path = std::env::temp_dir().join(format!("{}_sort.lock", std::process::id()));
+        let held = lock_file(&path).unwrap();
+        let other = File::create(&path).unwrap();
+        assert!(matches!(other.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(held);
+        assert!(other.try_lock().is_ok());
+        std::fs::remove_file(path).unwrap();
+    }
+}
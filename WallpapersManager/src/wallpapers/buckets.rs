@@ -0,0 +1,183 @@
+use {
+    serde::Deserialize,
+    std::path::{Path, PathBuf},
+};
+
+/// Limit between the default single and dual buckets.
+const RATIO_LIMIT: f64 = 16.0 / 9.0 * 1.3;
+
+/// A category of wallpapers, and the directory they're sorted into. In the config :
+///
+/// ```toml
+/// [[buckets]]
+/// name = "portrait"
+/// max_ratio = 0.9
+///
+/// [[buckets]]
+/// name = "16:9"
+/// dir = "sixteen_nine"
+/// min_ratio = 1.6
+/// max_ratio = 1.9
+/// min_width = 1920
+/// min_height = 1080
+///
+/// [[buckets]]
+/// name = "dual"
+/// min_ratio = 3.0
+/// max_ratio = 4.0
+/// spanning = true
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Bucket {
+    pub(crate) name: String,
+    /// Relative to the wallpapers dir, the name of the bucket by default
+    #[serde(default)]
+    dir: Option<String>,
+    /// Width divided by height, inclusive
+    #[serde(default)]
+    pub(crate) min_ratio: Option<f64>,
+    /// Width divided by height, exclusive
+    #[serde(default)]
+    pub(crate) max_ratio: Option<f64>,
+    #[serde(default)]
+    pub(crate) min_width: u32,
+    #[serde(default)]
+    pub(crate) min_height: u32,
+    /// Displayed across several monitors rather than on a single one
+    #[serde(default)]
+    pub(crate) spanning: bool,
+}
+
+impl Bucket {
+    /// The two buckets used when none are configured.
+    pub(crate) fn defaults(single_dir: &str, dual_dir: &str) -> Vec<Bucket> {
+        vec![
+            Bucket {
+                name: "single".to_string(),
+                dir: Some(single_dir.to_string()),
+                min_ratio: None,
+                max_ratio: Some(RATIO_LIMIT),
+                min_width: 0,
+                min_height: 0,
+                spanning: false,
+            },
+            Bucket {
+                name: "dual".to_string(),
+                dir: Some(dual_dir.to_string()),
+                min_ratio: Some(RATIO_LIMIT),
+                max_ratio: None,
+                min_width: 0,
+                min_height: 0,
+                spanning: true,
+            },
+        ]
+    }
+
+    pub(crate) fn dir(&self, wallpapers_dir: &Path) -> PathBuf {
+        wallpapers_dir.join(self.dir.as_ref().unwrap_or(&self.name))
+    }
+
+    fn fits_ratio(&self, ratio: f64) -> bool {
+        self.min_ratio.is_none_or(|min_ratio| ratio >= min_ratio)
+            && self.max_ratio.is_none_or(|max_ratio| ratio < max_ratio)
+    }
+
+    fn accepts(&self, width: u32, height: u32) -> bool {
+        width >= self.min_width
+            && height >= self.min_height
+            && self.fits_ratio(width as f64 / height as f64)
+    }
+}
+
+/// The first bucket an image of this size belongs to.
+pub(crate) fn classify(buckets: &[Bucket], width: u32, height: u32) -> Option<&Bucket> {
+    buckets.iter().find(|bucket| bucket.accepts(width, height))
+}
+
+/// The bucket whose wallpapers suit best a monitor, or a group of monitors when `spanning` : the
+/// first one with the right aspect ratio, or else the first one that is displayed that way.
+pub(crate) fn best_fit(
+    buckets: &[Bucket],
+    width: u32,
+    height: u32,
+    spanning: bool,
+) -> Option<&Bucket> {
+    let mut displayed_that_way = buckets.iter().filter(|bucket| bucket.spanning == spanning);
+    displayed_that_way
+        .clone()
+        .find(|bucket| bucket.fits_ratio(width as f64 / height as f64))
+        .or_else(|| displayed_that_way.next())
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    pub(crate) fn bucket(name: &str, min_ratio: f64, max_ratio: f64, spanning: bool) -> Bucket {
+        Bucket {
+            name: name.to_string(),
+            dir: None,
+            min_ratio: Some(min_ratio),
+            max_ratio: Some(max_ratio),
+            min_width: 0,
+            min_height: 0,
+            spanning,
+        }
+    }
+
+    pub(crate) fn buckets() -> Vec<Bucket> {
+        vec![
+            bucket("portrait", 0., 0.9, false),
+            bucket("4:3", 0.9, 1.5, false),
+            Bucket {
+                min_width: 1920,
+                min_height: 1080,
+                ..bucket("16:9", 1.5, 2.0, false)
+            },
+            bucket("ultrawide", 2.0, 3.0, false),
+            bucket("dual", 3.0, 4.0, true),
+            bucket("triple", 4.0, 6.0, true),
+        ]
+    }
+
+    fn name(bucket: Option<&Bucket>) -> Option<&str> {
+        bucket.map(|bucket| bucket.name.as_str())
+    }
+
+    #[test]
+    fn test_classify() {
+        let buckets = buckets();
+        assert_eq!(name(classify(&buckets, 1080, 1920)), Some("portrait"));
+        assert_eq!(name(classify(&buckets, 1600, 1200)), Some("4:3"));
+        assert_eq!(name(classify(&buckets, 3840, 2160)), Some("16:9"));
+        // Too small for 16:9
+        assert_eq!(name(classify(&buckets, 1280, 720)), None);
+        assert_eq!(name(classify(&buckets, 3440, 1440)), Some("ultrawide"));
+        assert_eq!(name(classify(&buckets, 5760, 1080)), Some("triple"));
+        assert_eq!(name(classify(&buckets, 10000, 1000)), None);
+    }
+
+    #[test]
+    fn test_default_buckets_keep_ratio_limit() {
+        let buckets = Bucket::defaults("single", "dual");
+        assert_eq!(name(classify(&buckets, 2400, 1080)), Some("single"));
+        assert_eq!(name(classify(&buckets, 3840, 1080)), Some("dual"));
+        assert_eq!(
+            buckets[1].dir(Path::new("/wallpapers")),
+            PathBuf::from("/wallpapers/dual")
+        );
+    }
+
+    #[test]
+    fn test_best_fit() {
+        let buckets = buckets();
+        assert_eq!(
+            name(best_fit(&buckets, 1080, 1920, false)),
+            Some("portrait")
+        );
+        assert_eq!(name(best_fit(&buckets, 3840, 1080, true)), Some("dual"));
+        // No bucket for 4 monitors, but a spanning one is still better than nothing
+        assert_eq!(name(best_fit(&buckets, 7680, 1080, true)), Some("dual"));
+        assert_eq!(name(best_fit(&buckets[..4], 3840, 1080, true)), None);
+    }
+}
@@ -1,23 +1,34 @@
 use crate::{
-    monitors::{screens_config, ScreensConfig},
+    catalogue::Catalogue,
+    daemon::{self, Request},
+    monitors::MonitorLayout,
+    setters::{setter, WallpaperSetter, Wallpapers},
     CONFIG,
 };
 
 use {
+    super::{
+        buckets::{best_fit, Bucket},
+        compose::{compose_to_file, split_across},
+        fit::{fitted, FitStrategy},
+        history::History,
+        ratings::weight,
+        selection::ShuffleBags,
+        sort::get_wallpaper_paths,
+    },
+    chrono::Utc,
     clap::ValueEnum,
-    don_error::{bail, try_or_report, DonResult, DonResultOptionExtensions},
+    don_error::*,
     rand::Rng,
+    serde::{Deserialize, Serialize},
     std::{
-        env::var,
-        fs::{create_dir_all, File},
-        path::PathBuf,
-        process::{Command, Stdio},
-        thread::sleep,
-        time::Duration,
+        collections::HashMap,
+        path::{Path, PathBuf},
     },
 };
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(ValueEnum, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Mode {
     OnlySingle,
     OnlyDual,
@@ -25,98 +36,548 @@ pub enum Mode {
     FiftyFifty,
 }
 
-pub fn once(mode: &Mode) -> DonResult<()> {
-    use {Mode::*, ScreensConfig::*};
-    match &screens_config()? {
-        SingleScreen | DualScreenDifferentResolution | ThreeScreensOrMore => choose_single(),
-        DualScreenSameResolution => match *mode {
-            OnlySingle => choose_single(),
-            OnlyDual => choose_dual(),
-            FiftyFifty => choose_single_or_dual_randomly(0.5),
-            ProportionateToNumberOfFiles => {
-                // We'll select 2 single wallpapers each time we run the 'feh' command, so we divide
-                // by 2 to get a similar probability for each wallpaper.
+/// What is displayed on some monitors, as indexes in the `MonitorLayout`.
+#[derive(Debug, PartialEq)]
+enum Placement {
+    /// A single screen wallpaper on one monitor
+    Single(usize),
+    /// A dual screen wallpaper spanning across adjacent monitors
+    Spanning(Vec<usize>),
+}
+
+/// Wallpapers along with the monitors they cover, as indexes in the `MonitorLayout`.
+pub(crate) type Images = Vec<(Vec<usize>, PathBuf)>;
+
+/// Which bucket the wallpapers of each placement are taken from.
+struct BucketChoice<'b> {
+    buckets: &'b [Bucket],
+    /// Bucket asked for on the command line
+    target: Option<&'b Bucket>,
+}
+
+impl BucketChoice<'_> {
+    fn for_monitor(&self, layout: &MonitorLayout, index: usize) -> DonResult<&Bucket> {
+        let monitor = &layout.monitors[index];
+        match self.target {
+            Some(target) if !target.spanning => Some(target),
+            _ => best_fit(self.buckets, monitor.width, monitor.height, false),
+        }
+        .ok_or_don_err(format!("No single screen bucket for {}", monitor.name))
+    }
+
+    fn for_group(&self, layout: &MonitorLayout, group: &[usize]) -> Option<&Bucket> {
+        match self.target {
+            Some(target) => target.spanning.then_some(target),
+            None => {
+                let bounds = layout.bounds(group);
+                best_fit(self.buckets, bounds.width, bounds.height, true)
+            }
+        }
+    }
+
+    fn for_placement(&self, layout: &MonitorLayout, placement: &Placement) -> DonResult<&Bucket> {
+        match placement {
+            Placement::Single(index) => self.for_monitor(layout, *index),
+            Placement::Spanning(group) => self
+                .for_group(layout, group)
+                .ok_or_don_err("No spanning bucket"),
+        }
+    }
+}
+
+/// Changes the wallpapers, taking them from `bucket` only if set. When a daemon is running, it's
+/// the one changing them, with its own mode.
+pub fn once(mode: &Mode, bucket: Option<&str>) -> DonResult<()> {
+    let request = Request::Next {
+        bucket: bucket.map(str::to_string),
+    };
+    match daemon::send(&request)? {
+        Some(message) => println!("{message}"),
+        None => {
+            change(mode, bucket)?;
+        }
+    }
+    Ok(())
+}
+
+/// Changes the wallpapers, and returns the ones displayed.
+pub(crate) fn change(mode: &Mode, bucket: Option<&str>) -> DonResult<Images> {
+    let layout = MonitorLayout::detect()?;
+    let buckets = CONFIG.buckets();
+    let target = match bucket {
+        Some(name) => Some(
+            buckets
+                .iter()
+                .find(|bucket| bucket.name == name)
+                .ok_or_don_err(format!("No bucket named {name}"))?,
+        ),
+        None => None,
+    };
+    let choice = BucketChoice {
+        buckets: &buckets,
+        target,
+    };
+    let setter = setter(CONFIG.wallpaper_setter);
+    // Setters like Plasma display the same image on every monitor : the one of the first monitor
+    if !setter.spans() && !setter.per_monitor() {
+        let placements = [Placement::Single(0)];
+        return display(&*setter, &layout, &choice, &placements);
+    }
+    let mut rng = rand::thread_rng();
+    let placements = plan(&layout, |group| {
+        let Some(spanning_bucket) = choice.for_group(&layout, group) else {
+            return false;
+        };
+        if target.is_some() {
+            return true;
+        }
+        match mode {
+            Mode::OnlySingle => false,
+            Mode::OnlyDual => true,
+            Mode::FiftyFifty => rng.gen::<f64>() > 0.5,
+            Mode::ProportionateToNumberOfFiles => {
+                // We'll select a single wallpaper per monitor of the group when we don't span, so
+                // we divide by the number of monitors to get a similar probability for each
+                // wallpaper.
                 //
-                // Ex : if we have 10 single wallpapers and 2 dual ones, for each to be selected
-                // once, we'll need to select 2 singles * 5 times + each dual once.
+                // Ex : if we have 10 single wallpapers and 2 dual ones for 2 monitors, for each to
+                // be selected once, we'll need to select 2 singles * 5 times + each dual once.
                 // So we need a proba of 5 out of 7 for Single and 2 out of 7 for Dual.
-                let nb_single_divided_by_two = PathBuf::from(&CONFIG.wallpapers_dir)
-                    .join(&CONFIG.single_screen_dir)
-                    .read_dir()?
-                    .count() as f64
-                    / 2.;
-                let nb_dual = PathBuf::from(&CONFIG.wallpapers_dir)
-                    .join(&CONFIG.dual_screen_dir)
-                    .read_dir()?
-                    .count() as f64;
-                choose_single_or_dual_randomly(
-                    nb_single_divided_by_two / (nb_single_divided_by_two + nb_dual),
-                )
+                let nb_single = choice
+                    .for_monitor(&layout, group[0])
+                    .map(count_files)
+                    .unwrap_or_default();
+                let nb_single_divided = nb_single / group.len() as f64;
+                let nb_dual = count_files(spanning_bucket);
+                rng.gen::<f64>() > nb_single_divided / (nb_single_divided + nb_dual)
             }
-        },
+        }
+    });
+    display(&*setter, &layout, &choice, &placements)
+}
+
+fn count_files(bucket: &Bucket) -> f64 {
+    get_wallpaper_paths(&bucket.dir(Path::new(&CONFIG.wallpapers_dir))).count() as f64
+}
+
+/// Spans a wallpaper across each group of monitors for which `span` says so, and puts a single
+/// wallpaper on every other monitor. Groups overlapping an already spanned one aren't offered.
+fn plan(layout: &MonitorLayout, mut span: impl FnMut(&[usize]) -> bool) -> Vec<Placement> {
+    let mut placements = vec![];
+    let mut spanned = vec![];
+    for group in layout.spanning_groups() {
+        if group.iter().any(|index| spanned.contains(index)) {
+            continue;
+        }
+        if span(&group) {
+            spanned.extend(group.iter().copied());
+            placements.push(Placement::Spanning(group));
+        }
     }
+    for index in 0..layout.monitors.len() {
+        if !spanned.contains(&index) {
+            placements.push(Placement::Single(index));
+        }
+    }
+    placements
 }
 
-pub fn every_n_min(minutes: u64, mode: &Mode) -> DonResult<()> {
-    let lock_file_path = PathBuf::from(format!("{}/.wallpapers-mgr/lock", var("HOME")?));
-    if !lock_file_path.exists() {
-        create_dir_all(
-            lock_file_path
-                .parent()
-                .ok_or_don_err("just created the path with a parent")?,
-        )?;
-        File::create(&lock_file_path)?;
-    }
-    let mut lock_file = fd_lock::RwLock::new(File::open(&lock_file_path)?);
-    let lock = lock_file.try_write();
-    // If the lock is taken, it means a cron is already running.
-    // In that case, we still want to change the wallpapers once but not start a second cron.
-    match lock {
-        Ok(_) => loop {
-            try_or_report(|| once(mode));
-            sleep(Duration::new(minutes * 60, 0));
-        },
-        Err(_) => once(mode),
+/// Displays the previous wallpapers again. When a daemon is running, it's the one doing it.
+pub fn previous() -> DonResult<()> {
+    match daemon::send(&Request::Previous)? {
+        Some(message) => println!("{message}"),
+        None => back()?,
     }
+    Ok(())
 }
 
-fn choose_single_or_dual_randomly(probability_to_chose_single_wallpapers: f64) -> DonResult<()> {
-    if rand::thread_rng().gen::<f64>() <= probability_to_chose_single_wallpapers {
-        choose_single()
-    } else {
-        choose_dual()
+/// Displays the previous wallpapers of the history again, and forgets the current ones.
+pub(crate) fn back() -> DonResult<()> {
+    let layout = MonitorLayout::detect()?;
+    let mut history = History::load()?;
+    let images = history.go_back(&layout)?;
+    show(
+        &*setter(CONFIG.wallpaper_setter),
+        &layout,
+        images,
+        CONFIG.fit_strategy,
+    )?;
+    history.save()
+}
+
+/// Picks the wallpapers for `placements`, displays them and records it in the history.
+fn display(
+    setter: &dyn WallpaperSetter,
+    layout: &MonitorLayout,
+    choice: &BucketChoice,
+    placements: &[Placement],
+) -> DonResult<Images> {
+    let catalogue = Catalogue::open()?;
+    let mut picking = Picking {
+        catalogue: &catalogue,
+        avoid_recent: CONFIG.recently_displayed_to_avoid,
+        shuffle_bags: ShuffleBags::load()?,
+        history: History::load()?,
+    };
+    let images = pick_and_show(
+        setter,
+        layout,
+        choice,
+        placements,
+        Path::new(&CONFIG.wallpapers_dir),
+        &mut picking,
+        CONFIG.fit_strategy,
+    )?;
+    picking.shuffle_bags.save()?;
+    picking.history.save()?;
+    Ok(images)
+}
+
+/// What the wallpapers are picked with, and where what is displayed is recorded.
+struct Picking<'c> {
+    catalogue: &'c Catalogue,
+    /// How many of the last displayed wallpapers are avoided
+    avoid_recent: usize,
+    shuffle_bags: ShuffleBags,
+    history: History,
+}
+
+fn pick_and_show(
+    setter: &dyn WallpaperSetter,
+    layout: &MonitorLayout,
+    choice: &BucketChoice,
+    placements: &[Placement],
+    wallpapers_dir: &Path,
+    picking: &mut Picking,
+    strategy: FitStrategy,
+) -> DonResult<Images> {
+    let recent = picking.catalogue.recent_displays(picking.avoid_recent)?;
+    let ratings = picking.catalogue.ratings()?;
+
+    let mut by_bucket = HashMap::<PathBuf, Vec<usize>>::new();
+    for (position, placement) in placements.iter().enumerate() {
+        let dir = choice.for_placement(layout, placement)?.dir(wallpapers_dir);
+        by_bucket.entry(dir).or_default().push(position);
+    }
+    let mut picked = vec![PathBuf::new(); placements.len()];
+    for (dir, positions) in by_bucket {
+        let candidates = get_wallpaper_paths(&dir).collect::<Vec<_>>();
+        let wallpapers = picking.shuffle_bags.bag(&dir).pick(
+            &candidates,
+            positions.len(),
+            &recent,
+            |path| weight(ratings.get(path)),
+            &mut rand::thread_rng(),
+        )?;
+        for (position, wallpaper) in positions.into_iter().zip(wallpapers) {
+            picked[position] = wallpaper;
+        }
+    }
+
+    let images: Images = placements
+        .iter()
+        .zip(&picked)
+        .map(|(placement, wallpaper)| match placement {
+            Placement::Single(index) => (vec![*index], wallpaper.clone()),
+            Placement::Spanning(group) => (group.clone(), wallpaper.clone()),
+        })
+        .collect();
+    show(setter, layout, images.clone(), strategy)?;
+
+    for path in &picked {
+        picking.catalogue.record_display(path)?;
     }
+    picking.history.record(layout, &images, Utc::now());
+    Ok(images)
 }
 
-fn choose_single() -> DonResult<()> {
-    run_feh_command(&[
-        "--bg-max",
-        "--no-fehbg",
-        "--random",
-        &format!("{}/{}", CONFIG.wallpapers_dir, CONFIG.single_screen_dir),
-    ])
+/// Hands the wallpapers, along with the monitors they cover, to `setter` : either as a composed
+/// image of the whole desktop or with spanning wallpapers cut into the part each monitor displays.
+/// Wallpapers are fitted to their monitors with `strategy`.
+fn show(
+    setter: &dyn WallpaperSetter,
+    layout: &MonitorLayout,
+    images: Images,
+    strategy: FitStrategy,
+) -> DonResult<()> {
+    // Setters like Plasma display the same image on every monitor : the one of the first monitor
+    if !setter.spans() && !setter.per_monitor() && layout.monitors.len() > 1 {
+        let (_, path) = images
+            .iter()
+            .find(|(group, _)| group.contains(&0))
+            .ok_or_don_err("No wallpaper for the first monitor")?;
+        let monitor = &layout.monitors[0];
+        let image = fitted(path, monitor.width, monitor.height, strategy)?;
+        return setter.set(
+            layout,
+            &Wallpapers::PerMonitor(vec![image; layout.monitors.len()]),
+        );
+    }
+    if composes(setter, layout, &images) {
+        let images = images
+            .iter()
+            .map(|(group, path)| (group.clone(), path.as_path()))
+            .collect::<Vec<_>>();
+        let desktop = compose_to_file(layout, &images, strategy)?;
+        return setter.set(layout, &Wallpapers::Spanning(desktop));
+    }
+    let mut by_monitor = vec![PathBuf::new(); layout.monitors.len()];
+    for (group, path) in images {
+        match group.as_slice() {
+            [index] => {
+                let monitor = &layout.monitors[*index];
+                by_monitor[*index] = fitted(&path, monitor.width, monitor.height, strategy)?
+            }
+            _ => {
+                let slices = split_across(layout, &group, &path, strategy)?;
+                for (index, slice) in group.iter().zip(slices) {
+                    by_monitor[*index] = slice;
+                }
+            }
+        }
+    }
+    setter.set(layout, &Wallpapers::PerMonitor(by_monitor))
 }
 
-fn choose_dual() -> DonResult<()> {
-    run_feh_command(&[
-        "--bg-max",
-        "--no-fehbg",
-        "--no-xinerama",
-        "--random",
-        &format!("{}/{}", CONFIG.wallpapers_dir, CONFIG.dual_screen_dir),
-    ])
+/// Whether we compose the whole desktop ourselves : when the setter can display a composed image
+/// and can't display what we want as it is.
+fn composes(
+    setter: &dyn WallpaperSetter,
+    layout: &MonitorLayout,
+    images: &[(Vec<usize>, PathBuf)],
+) -> bool {
+    let spans = images.iter().any(|(group, _)| group.len() > 1);
+    setter.spans() && layout.monitors.len() > 1 && (spans || !setter.per_monitor())
 }
 
-fn run_feh_command(args: &[&str]) -> DonResult<()> {
-    let output = Command::new("feh")
-        .args(args)
-        .stdout(Stdio::null())
-        .output()?;
-    if !output.status.success() {
-        bail!(
-            "Command didn't execute successfully : {:#?}",
-            String::from_utf8(output.stderr)?
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use {
+        super::super::{buckets::test::buckets, history::Shown},
+        crate::{monitors::Monitor, setters::test_helpers::RecordingSetter},
+        std::{
+            collections::HashSet,
+            fs::{create_dir_all, remove_dir_all, write},
+        },
+    };
+
+    fn three_monitors() -> MonitorLayout {
+        MonitorLayout {
+            monitors: vec![
+                Monitor::new("DP-1", 1920, 1080, 0, 0),
+                Monitor::new("DP-2", 1920, 1080, 1920, 0),
+                // Below the others
+                Monitor::new("DP-3", 1080, 1920, 3840, 1080),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_plan_spans_only_where_asked() {
+        let layout = three_monitors();
+        assert_eq!(
+            plan(&layout, |_| true),
+            vec![Placement::Spanning(vec![0, 1]), Placement::Single(2)]
+        );
+        assert_eq!(
+            plan(&layout, |_| false),
+            vec![
+                Placement::Single(0),
+                Placement::Single(1),
+                Placement::Single(2)
+            ]
         );
     }
-    Ok(())
+
+    #[test]
+    fn test_plan_spans_part_of_a_row() {
+        let layout = MonitorLayout {
+            monitors: vec![
+                Monitor::new("DP-1", 1920, 1080, 0, 0),
+                Monitor::new("DP-2", 1920, 1080, 1920, 0),
+                Monitor::new("DP-3", 1920, 1080, 3840, 0),
+            ],
+        };
+        assert_eq!(
+            plan(&layout, |group| group == [1, 2]),
+            vec![Placement::Spanning(vec![1, 2]), Placement::Single(0)]
+        );
+        // The whole row first, then nothing overlapping it
+        let mut offered = vec![];
+        plan(&layout, |group| {
+            offered.push(group.to_vec());
+            true
+        });
+        assert_eq!(offered, [vec![0, 1, 2]]);
+    }
+
+    fn images(placements: &[Placement]) -> Images {
+        placements
+            .iter()
+            .map(|placement| match placement {
+                Placement::Single(index) => (vec![*index], PathBuf::from(format!("{index}.jpg"))),
+                Placement::Spanning(group) => (group.clone(), PathBuf::from("spanning.jpg")),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bucket_choice() {
+        let (layout, buckets) = (three_monitors(), buckets());
+        let choice = BucketChoice {
+            buckets: &buckets,
+            target: None,
+        };
+        let names = |placements: &[Placement]| {
+            placements
+                .iter()
+                .map(|placement| &choice.for_placement(&layout, placement).unwrap().name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&plan(&layout, |_| true)), ["dual", "portrait"]);
+        assert_eq!(
+            names(&plan(&layout, |_| false)),
+            ["16:9", "16:9", "portrait"]
+        );
+
+        let target = BucketChoice {
+            buckets: &buckets,
+            target: Some(&buckets[3]),
+        };
+        assert!(target.for_group(&layout, &[0, 1]).is_none());
+        assert_eq!(target.for_monitor(&layout, 2).unwrap().name, "ultrawide");
+    }
+
+    #[test]
+    fn test_composes_only_when_needed() {
+        let layout = three_monitors();
+        let spanning = images(&plan(&layout, |_| true));
+        let singles = images(&plan(&layout, |_| false));
+        let feh = RecordingSetter {
+            spans: true,
+            ..Default::default()
+        };
+        assert!(composes(&feh, &layout, &spanning));
+        assert!(!composes(&feh, &layout, &singles));
+        let gnome = RecordingSetter {
+            spans: true,
+            single_image: true,
+            ..Default::default()
+        };
+        assert!(composes(&gnome, &layout, &singles));
+        let swaybg = RecordingSetter::default();
+        assert!(!composes(&swaybg, &layout, &spanning));
+    }
+
+    #[test]
+    fn test_show_same_image_everywhere_without_spanning_nor_per_monitor() {
+        let setter = RecordingSetter {
+            single_image: true,
+            spans: false,
+            ..Default::default()
+        };
+        let layout = MonitorLayout {
+            monitors: three_monitors().monitors[..2].to_vec(),
+        };
+        for placements in [plan(&layout, |_| false), plan(&layout, |_| true)] {
+            let first = images(&placements)[0].1.clone();
+            show(&setter, &layout, images(&placements), FitStrategy::Off).unwrap();
+            assert_eq!(
+                setter.displayed.take(),
+                vec![Wallpapers::PerMonitor(vec![first.clone(), first])]
+            );
+        }
+    }
+
+    #[test]
+    fn test_show_one_single_per_monitor() {
+        let setter = RecordingSetter::default();
+        let layout = three_monitors();
+        show(
+            &setter,
+            &layout,
+            images(&plan(&layout, |_| false)),
+            FitStrategy::Off,
+        )
+        .unwrap();
+        assert_eq!(
+            setter.displayed.take(),
+            vec![Wallpapers::PerMonitor(vec![
+                PathBuf::from("0.jpg"),
+                PathBuf::from("1.jpg"),
+                PathBuf::from("2.jpg"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_single_image_setter_picks_only_what_it_shows() {
+        let wallpapers_dir =
+            std::env::temp_dir().join(format!("{}_single_image", std::process::id()));
+        let dir = wallpapers_dir.join("16:9");
+        create_dir_all(&dir).unwrap();
+        let candidates = ["a.jpg", "b.jpg", "c.jpg"].map(|name| dir.join(name));
+        for path in &candidates {
+            write(path, b"").unwrap();
+        }
+        let setter = RecordingSetter {
+            single_image: true,
+            ..Default::default()
+        };
+        let layout = MonitorLayout {
+            monitors: three_monitors().monitors[..2].to_vec(),
+        };
+        let buckets = buckets();
+        let choice = BucketChoice {
+            buckets: &buckets,
+            target: None,
+        };
+        let catalogue = Catalogue::open_in_memory().unwrap();
+        let mut picking = Picking {
+            catalogue: &catalogue,
+            avoid_recent: 0,
+            shuffle_bags: ShuffleBags::default(),
+            history: History::default(),
+        };
+        let images = pick_and_show(
+            &setter,
+            &layout,
+            &choice,
+            &[Placement::Single(0)],
+            &wallpapers_dir,
+            &mut picking,
+            FitStrategy::Off,
+        )
+        .unwrap();
+        let [(group, shown)] = images.as_slice() else {
+            panic!("{images:?}");
+        };
+        assert_eq!(group, &[0]);
+        assert_eq!(
+            setter.displayed.take(),
+            vec![Wallpapers::PerMonitor(vec![shown.clone(), shown.clone()])]
+        );
+        assert_eq!(catalogue.recent_displays(10).unwrap(), vec![shown.clone()]);
+        assert_eq!(
+            picking.history.current().unwrap().wallpapers,
+            [Shown {
+                monitors: vec!["DP-1".to_string()],
+                path: shown.clone(),
+            }]
+        );
+        // Only one ticket was drawn from the bag : the two others are left in this cycle
+        let others = picking
+            .shuffle_bags
+            .bag(&dir)
+            .pick(&candidates, 2, &[], |_| 1, &mut rand::thread_rng())
+            .unwrap();
+        assert!(!others.contains(shown));
+        assert_eq!(
+            others.iter().chain([shown]).collect::<HashSet<_>>(),
+            candidates.iter().collect()
+        );
+        remove_dir_all(wallpapers_dir).unwrap();
+    }
 }
@@ -0,0 +1,200 @@
+use crate::{monitors::MonitorLayout, state::state_dir};
+
+use {
+    super::fit::{fit, FitStrategy},
+    don_error::*,
+    image::{imageops, DynamicImage, RgbImage},
+    std::{
+        fs::{create_dir_all, read_dir, remove_file},
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Fits `image` to the monitors of `group`, and cuts it into the part each of them displays, as if
+/// they were windows onto the same picture.
+fn parts(
+    layout: &MonitorLayout,
+    group: &[usize],
+    image: &DynamicImage,
+    strategy: FitStrategy,
+) -> Vec<(usize, DynamicImage)> {
+    let bounds = layout.bounds(group);
+    let image = fit(image, bounds.width, bounds.height, strategy);
+    group
+        .iter()
+        .map(|&index| {
+            let monitor = &layout.monitors[index];
+            (
+                index,
+                image.crop_imm(
+                    (monitor.x - bounds.x) as u32,
+                    (monitor.y - bounds.y) as u32,
+                    monitor.width,
+                    monitor.height,
+                ),
+            )
+        })
+        .collect()
+}
+
+/// The whole desktop, each image covering its monitors. What's outside of the monitors is black.
+pub(crate) fn compose(
+    layout: &MonitorLayout,
+    images: &[(Vec<usize>, DynamicImage)],
+    strategy: FitStrategy,
+) -> RgbImage {
+    let desktop = layout.bounds(&layout.all());
+    let mut canvas = RgbImage::new(desktop.width, desktop.height);
+    for (group, image) in images {
+        for (index, part) in parts(layout, group, image, strategy) {
+            let monitor = &layout.monitors[index];
+            imageops::replace(
+                &mut canvas,
+                &part.into_rgb8(),
+                (monitor.x - desktop.x) as i64,
+                (monitor.y - desktop.y) as i64,
+            );
+        }
+    }
+    canvas
+}
+
+/// Writes the parts each monitor of `group` displays to the state dir, under new names each time
+/// like `compose_to_file`.
+pub(crate) fn split_across(
+    layout: &MonitorLayout,
+    group: &[usize],
+    path: &Path,
+    strategy: FitStrategy,
+) -> DonResult<Vec<PathBuf>> {
+    let dir = cache_dir("spanning")?;
+    let mut slices = vec![];
+    for (index, part) in parts(layout, group, &image::open(path)?, strategy) {
+        let slice_path = fresh_path(&dir, &layout.monitors[index].name)?;
+        part.save(&slice_path)?;
+        slices.push(slice_path);
+    }
+    Ok(slices)
+}
+
+/// Composes the desktop and writes it to the state dir, under a new name each time as some
+/// desktops don't reload a wallpaper whose path didn't change.
+pub(crate) fn compose_to_file(
+    layout: &MonitorLayout,
+    images: &[(Vec<usize>, &Path)],
+    strategy: FitStrategy,
+) -> DonResult<PathBuf> {
+    let mut opened = vec![];
+    for (group, path) in images {
+        opened.push((group.clone(), image::open(path)?));
+    }
+    let dir = cache_dir("desktop")?;
+    for previous in read_dir(&dir)? {
+        remove_file(previous?.path())?;
+    }
+    let path = dir.join(format!(
+        "{}.png",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis()
+    ));
+    compose(layout, &opened, strategy).save(&path)?;
+    Ok(path)
+}
+
+/// A path in `dir` that `name` never had, its previous files being removed.
+fn fresh_path(dir: &Path, name: &str) -> DonResult<PathBuf> {
+    let prefix = format!("{name}_");
+    for previous in read_dir(dir)? {
+        let previous = previous?.path();
+        if previous
+            .file_name()
+            .is_some_and(|file_name| file_name.to_string_lossy().starts_with(&prefix))
+        {
+            remove_file(previous)?;
+        }
+    }
+    Ok(dir.join(format!(
+        "{prefix}{}.png",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+    )))
+}
+
+fn cache_dir(name: &str) -> DonResult<PathBuf> {
+    let dir = state_dir()?.join(name);
+    if !dir.exists() {
+        create_dir_all(&dir)?;
+    }
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use {
+        crate::monitors::Monitor,
+        image::{ImageBuffer, Rgb},
+    };
+
+    // A laptop on the left of a taller external monitor, vertically centred on it
+    fn layout() -> MonitorLayout {
+        MonitorLayout {
+            monitors: vec![
+                Monitor::new("eDP-1", 40, 20, 0, 10),
+                Monitor::new("DP-1", 40, 40, 40, 0),
+            ],
+        }
+    }
+
+    fn plain(width: u32, height: u32, color: [u8; 3]) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_pixel(width, height, Rgb(color)))
+    }
+
+    #[test]
+    fn test_spanning_image_respects_offsets() {
+        // Left half red, right half blue
+        let panoramic =
+            DynamicImage::ImageRgb8(ImageBuffer::from_fn(160, 80, |x, _| match x < 80 {
+                true => Rgb([255, 0, 0]),
+                false => Rgb([0, 0, 255]),
+            }));
+        let desktop = compose(&layout(), &[(vec![0, 1], panoramic)], FitStrategy::Fill);
+        assert_eq!(desktop.dimensions(), (80, 40));
+        assert_eq!(desktop.get_pixel(20, 20), &Rgb([255, 0, 0]));
+        assert_eq!(desktop.get_pixel(60, 5), &Rgb([0, 0, 255]));
+        // Above and below the laptop
+        assert_eq!(desktop.get_pixel(20, 5), &Rgb([0, 0, 0]));
+        assert_eq!(desktop.get_pixel(20, 35), &Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_singles_side_by_side() {
+        let desktop = compose(
+            &layout(),
+            &[
+                (vec![0], plain(1920, 1080, [0, 255, 0])),
+                (vec![1], plain(1000, 1000, [255, 255, 255])),
+            ],
+            FitStrategy::Fill,
+        );
+        assert_eq!(desktop.get_pixel(0, 10), &Rgb([0, 255, 0]));
+        assert_eq!(desktop.get_pixel(39, 29), &Rgb([0, 255, 0]));
+        assert_eq!(desktop.get_pixel(40, 0), &Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn test_slices_get_fresh_paths() {
+        let dir = std::env::temp_dir().join(format!("{}_slices", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let first = fresh_path(&dir, "DP-1").unwrap();
+        plain(1, 1, [0, 0, 0]).save(&first).unwrap();
+        let other_monitor = fresh_path(&dir, "DP-10").unwrap();
+        plain(1, 1, [0, 0, 0]).save(&other_monitor).unwrap();
+
+        let second = fresh_path(&dir, "DP-1").unwrap();
+        assert_ne!(first, second);
+        assert!(!first.exists());
+        assert!(other_monitor.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
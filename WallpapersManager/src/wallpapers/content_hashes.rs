@@ -0,0 +1,70 @@
+use {
+    super::{file_cache::FileCache, sort::get_wallpaper_paths},
+    don_error::*,
+    std::{
+        fs::read,
+        path::{Path, PathBuf},
+    },
+};
+
+const CACHE_FILE: &str = "content_hashes.json";
+
+/// Sha256 of every file of the library.
+pub(crate) struct ContentHashes {
+    cache: FileCache<String>,
+}
+
+impl ContentHashes {
+    /// Loads the cache and brings it up to date with the content of `dir`.
+    pub(crate) fn load(dir: &Path) -> DonResult<Self> {
+        Ok(ContentHashes {
+            cache: FileCache::load(CACHE_FILE, get_wallpaper_paths(dir), |path| {
+                Ok(hash(&read(path)?))
+            })?,
+        })
+    }
+
+    /// Loads the cache as it is, without bringing it up to date.
+    pub(crate) fn open() -> DonResult<Self> {
+        Ok(ContentHashes {
+            cache: FileCache::open(CACHE_FILE)?,
+        })
+    }
+
+    /// The hash of `path`, only computed when the file was modified since it was cached, without
+    /// bringing the cache of the whole library up to date.
+    pub(crate) fn of(path: &Path) -> DonResult<String> {
+        let mut content_hashes = ContentHashes::open()?;
+        if let Some(hash) = content_hashes.cache.get(path)? {
+            return Ok(hash.clone());
+        }
+        let hash = hash(&read(path)?);
+        content_hashes.insert(path.to_owned(), hash.clone())?;
+        content_hashes.save()?;
+        Ok(hash)
+    }
+
+    pub(crate) fn save(&self) -> DonResult<()> {
+        self.cache.save(CACHE_FILE)
+    }
+
+    /// Returns a file of the library with this content hash, if any.
+    pub(crate) fn find(&self, hash: &str) -> Option<&Path> {
+        self.cache
+            .iter()
+            .find(|(_, file_hash)| *file_hash == hash)
+            .map(|(path, _)| path)
+    }
+
+    pub(crate) fn insert(&mut self, path: PathBuf, hash: String) -> DonResult<()> {
+        self.cache.insert(path, hash)
+    }
+
+    pub(crate) fn remove(&mut self, path: &Path) {
+        self.cache.remove(path)
+    }
+}
+
+pub(crate) fn hash(bytes: &[u8]) -> String {
+    sha256::digest(bytes)
+}
@@ -0,0 +1,209 @@
+use crate::{catalogue::Catalogue, CONFIG};
+
+use {
+    super::{content_hashes::ContentHashes, file_cache::FileCache, sort::get_wallpaper_paths},
+    clap::ValueEnum,
+    don_error::*,
+    image::{imageops::FilterType, DynamicImage},
+    std::{
+        fs::{metadata, remove_file},
+        path::PathBuf,
+    },
+};
+
+const CACHE_FILE: &str = "perceptual_hashes.json";
+
+/// Which file of a group of near-duplicates is kept.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Policy {
+    /// Highest resolution, then biggest file
+    KeepLargest,
+    /// Most recently modified file
+    KeepNewest,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PerceptualHash {
+    hash: u64,
+    width: u32,
+    height: u32,
+}
+
+/// Finds groups of wallpapers that look the same (resized or recompressed copies), and removes all
+/// of them but one according to `policy`. With `dry_run`, only prints what would be removed.
+pub fn perform(policy: Policy, max_distance: u32, dry_run: bool) -> DonResult<()> {
+    let wallpapers_path = PathBuf::from(&CONFIG.wallpapers_dir);
+    if !wallpapers_path.exists() {
+        bail!("{} not found on this computer", &CONFIG.wallpapers_dir);
+    }
+    let hashes = FileCache::load(CACHE_FILE, get_wallpaper_paths(&wallpapers_path), |path| {
+        let image = image::open(path)?;
+        Ok(PerceptualHash {
+            hash: dhash(&image),
+            width: image.width(),
+            height: image.height(),
+        })
+    })?;
+    hashes.save(CACHE_FILE)?;
+
+    let files = hashes.iter().collect::<Vec<_>>();
+    let groups = group_near_duplicates(
+        &files
+            .iter()
+            .map(|(_, perceptual_hash)| perceptual_hash.hash)
+            .collect::<Vec<_>>(),
+        max_distance,
+    );
+    if groups.is_empty() {
+        println!("No near-duplicates found");
+        return Ok(());
+    }
+
+    let catalogue = Catalogue::open()?;
+    let mut content_hashes = ContentHashes::open()?;
+    let mut nb_removed = 0;
+    for group in groups {
+        let mut group = group
+            .into_iter()
+            .map(|index| files[index])
+            .collect::<Vec<_>>();
+        let kept_index = match policy {
+            Policy::KeepLargest => max_index_by_key(&group, |(path, perceptual_hash)| {
+                Ok((
+                    perceptual_hash.width as u64 * perceptual_hash.height as u64,
+                    metadata(path)?.len(),
+                ))
+            })?,
+            Policy::KeepNewest => {
+                max_index_by_key(&group, |(path, _)| Ok(metadata(path)?.modified()?))?
+            }
+        };
+        let (kept, _) = group.remove(kept_index);
+        println!("Keeping {kept:?}");
+        for (path, perceptual_hash) in group {
+            println!(
+                "  {} {path:?} ({}x{})",
+                if dry_run { "Would remove" } else { "Removing" },
+                perceptual_hash.width,
+                perceptual_hash.height
+            );
+            if !dry_run {
+                remove_file(path)?;
+                catalogue.record_removal(path)?;
+                content_hashes.remove(path);
+            }
+            nb_removed += 1;
+        }
+    }
+    content_hashes.save()?;
+    println!(
+        "{nb_removed} near-duplicate(s) {}",
+        if dry_run { "found" } else { "removed" }
+    );
+
+    Ok(())
+}
+
+fn max_index_by_key<T, K: Ord>(items: &[T], key: impl Fn(&T) -> DonResult<K>) -> DonResult<usize> {
+    let mut keys = Vec::with_capacity(items.len());
+    for item in items {
+        keys.push(key(item)?);
+    }
+    Ok(keys
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.cmp(b))
+        .map(|(index, _)| index)
+        .expect("Groups have at least two files"))
+}
+
+/// Difference hash: each bit tells whether a pixel is brighter than its right neighbour, on a 9x8
+/// grayscale thumbnail. Resizing or recompressing an image barely changes it.
+pub(crate) fn dhash(image: &DynamicImage) -> u64 {
+    let thumbnail = image.resize_exact(9, 8, FilterType::Triangle).into_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if thumbnail.get_pixel(x, y)[0] > thumbnail.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Groups (as indexes in `hashes`) of hashes all at most `max_distance` bits apart from each
+/// other, so that a chain of slightly different images doesn't put very different ones together.
+/// Hashes without any near-duplicate are left out.
+fn group_near_duplicates(hashes: &[u64], max_distance: u32) -> Vec<Vec<usize>> {
+    let is_near = |i: usize, j: usize| (hashes[i] ^ hashes[j]).count_ones() <= max_distance;
+    let mut remaining = (0..hashes.len()).collect::<Vec<_>>();
+    let mut groups = vec![];
+    while !remaining.is_empty() {
+        let mut group = vec![remaining.remove(0)];
+        remaining.retain(|&index| {
+            let joins = group.iter().all(|&member| is_near(member, index));
+            if joins {
+                group.push(index);
+            }
+            !joins
+        });
+        if group.len() > 1 {
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use image::{ImageBuffer, Rgb};
+
+    fn landscape(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+            let (x, y) = (x as f64 / width as f64, y as f64 / height as f64);
+            // A sun on a gradient sky
+            if (x - 0.7).powi(2) + (y - 0.3).powi(2) < 0.02 {
+                Rgb([250, 220, 50])
+            } else {
+                Rgb([(x * 100.) as u8, (y * 200.) as u8, 255 - (y * 150.) as u8])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_dhash_resists_resizing() {
+        let original = dhash(&landscape(1920, 1080));
+        let resized = dhash(&landscape(640, 360));
+        let flipped = dhash(&landscape(1920, 1080).fliph());
+        assert!((original ^ resized).count_ones() <= 4);
+        assert!((original ^ flipped).count_ones() > 10);
+    }
+
+    #[test]
+    fn test_group_near_duplicates() {
+        let hashes = [
+            0b0000_0000,
+            0b1111_0000_1111,
+            0b0000_0011,
+            0b1111_0000_1110,
+            // Near 2 but not 0 : left out
+            0b0000_1111,
+        ];
+        assert_eq!(
+            group_near_duplicates(&hashes, 2),
+            vec![vec![0, 2], vec![1, 3]]
+        );
+        assert!(group_near_duplicates(&hashes, 0).is_empty());
+    }
+
+    #[test]
+    fn test_chain_of_near_duplicates_is_split() {
+        // 0 is near 1 and 1 near 2, but 0 and 2 are 6 bits apart
+        let hashes = [0b0000_0000, 0b0000_0111, 0b0011_1111];
+        assert_eq!(group_near_duplicates(&hashes, 3), vec![vec![0, 1]]);
+    }
+}
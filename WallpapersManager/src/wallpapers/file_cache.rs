@@ -0,0 +1,131 @@
+use crate::state::{load_json, save_json};
+
+use {
+    don_error::*,
+    serde::{de::DeserializeOwned, Deserialize, Serialize},
+    std::{
+        collections::HashMap,
+        fs::metadata,
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+};
+
+/// Values computed from the content of files, stored in the state dir so that a value is only
+/// computed again when its file was modified.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct FileCache<V> {
+    files: HashMap<PathBuf, CachedValue<V>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedValue<V> {
+    size: u64,
+    modified: SystemTime,
+    value: V,
+}
+
+impl<V> Default for FileCache<V> {
+    fn default() -> Self {
+        FileCache {
+            files: HashMap::new(),
+        }
+    }
+}
+
+impl<V: Serialize + DeserializeOwned> FileCache<V> {
+    /// Loads `cache_file` and brings it up to date with `paths`: values of new or modified files
+    /// are computed, and files that don't exist anymore are dropped. Files for which `compute`
+    /// fails are reported and left out.
+    pub(crate) fn load(
+        cache_file: &str,
+        paths: impl Iterator<Item = PathBuf>,
+        compute: impl Fn(&Path) -> DonResult<V>,
+    ) -> DonResult<Self> {
+        let mut cached = FileCache::open(cache_file)?;
+        let mut file_cache = FileCache::default();
+        for path in paths {
+            try_or_report(|| {
+                let metadata = metadata(&path)?;
+                let (size, modified) = (metadata.len(), metadata.modified()?);
+                let cached_value = match cached.files.remove(&path) {
+                    Some(cached_value)
+                        if cached_value.size == size && cached_value.modified == modified =>
+                    {
+                        cached_value
+                    }
+                    _ => CachedValue {
+                        size,
+                        modified,
+                        value: compute(&path).err_ctx_val("path", path.to_string_lossy())?,
+                    },
+                };
+                file_cache.files.insert(path, cached_value);
+                Ok(())
+            });
+        }
+        Ok(file_cache)
+    }
+
+    /// Loads `cache_file` as it is.
+    pub(crate) fn open(cache_file: &str) -> DonResult<Self> {
+        load_json(cache_file)
+    }
+
+    pub(crate) fn save(&self, cache_file: &str) -> DonResult<()> {
+        save_json(cache_file, self)
+    }
+
+    /// The value of `path`, unless the file was modified since it was computed.
+    pub(crate) fn get(&self, path: &Path) -> DonResult<Option<&V>> {
+        let metadata = metadata(path)?;
+        let (size, modified) = (metadata.len(), metadata.modified()?);
+        Ok(self
+            .files
+            .get(path)
+            .filter(|cached_value| cached_value.size == size && cached_value.modified == modified)
+            .map(|cached_value| &cached_value.value))
+    }
+
+    pub(crate) fn insert(&mut self, path: PathBuf, value: V) -> DonResult<()> {
+        let metadata = metadata(&path)?;
+        self.files.insert(
+            path,
+            CachedValue {
+                size: metadata.len(),
+                modified: metadata.modified()?,
+                value,
+            },
+        );
+        Ok(())
+    }
+
+    pub(crate) fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Path, &V)> {
+        self.files
+            .iter()
+            .map(|(path, cached_value)| (path.as_path(), &cached_value.value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs::{remove_file, write};
+
+    #[test]
+    fn test_modified_files_are_not_cached() {
+        let path = std::env::temp_dir().join(format!("{}_file_cache", std::process::id()));
+        write(&path, b"a").unwrap();
+        let mut file_cache = FileCache::default();
+        file_cache.insert(path.clone(), 1).unwrap();
+        assert_eq!(file_cache.get(&path).unwrap(), Some(&1));
+        write(&path, b"ab").unwrap();
+        assert_eq!(file_cache.get(&path).unwrap(), None);
+        remove_file(path).unwrap();
+    }
+}
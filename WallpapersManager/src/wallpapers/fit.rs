@@ -0,0 +1,325 @@
+use crate::state::state_dir;
+
+use {
+    super::content_hashes::ContentHashes,
+    don_error::*,
+    image::{imageops::FilterType, DynamicImage, GenericImageView, GrayImage, RgbImage},
+    serde::Deserialize,
+    std::{
+        fs::{create_dir_all, read_dir, remove_file, File},
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    },
+};
+
+const CACHE_DIR: &str = "fitted";
+
+/// Fitted images that haven't been displayed for this long are removed from the cache.
+const UNUSED_FOR: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Size of the thumbnail the focus of smart crops is looked for on.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// How a wallpaper is made to match the resolution of a monitor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FitStrategy {
+    /// Left to the wallpaper setter
+    #[default]
+    Off,
+    /// Scaled to cover the monitor, and cropped around its center
+    Fill,
+    /// Scaled to fit in the monitor, with black bars
+    Fit,
+    /// Scaled to cover the monitor, and cropped around its most detailed part
+    EntropyCrop,
+    /// Scaled to cover the monitor, and cropped around its sharpest edges
+    EdgeCrop,
+}
+
+impl FitStrategy {
+    fn name(&self) -> &str {
+        match self {
+            FitStrategy::Off => "off",
+            FitStrategy::Fill => "fill",
+            FitStrategy::Fit => "fit",
+            FitStrategy::EntropyCrop => "entropy",
+            FitStrategy::EdgeCrop => "edge",
+        }
+    }
+}
+
+/// `image` at exactly `width` x `height`. `Off` is treated as `Fill`.
+pub(crate) fn fit(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    strategy: FitStrategy,
+) -> DynamicImage {
+    match strategy {
+        FitStrategy::Off | FitStrategy::Fill => {
+            image.resize_to_fill(width, height, FilterType::Lanczos3)
+        }
+        FitStrategy::Fit => {
+            let resized = image.resize(width, height, FilterType::Lanczos3);
+            let mut canvas = RgbImage::new(width, height);
+            image::imageops::replace(
+                &mut canvas,
+                &resized.to_rgb8(),
+                ((width - resized.width()) / 2) as i64,
+                ((height - resized.height()) / 2) as i64,
+            );
+            DynamicImage::ImageRgb8(canvas)
+        }
+        FitStrategy::EntropyCrop | FitStrategy::EdgeCrop => {
+            smart_crop(image, width, height, strategy)
+        }
+    }
+}
+
+/// Cached version of `path` fitted to `width` x `height`, created if needed. Cached versions are
+/// named after the content hash of `path`, so that they follow it when it's moved.
+pub(crate) fn fitted(
+    path: &Path,
+    width: u32,
+    height: u32,
+    strategy: FitStrategy,
+) -> DonResult<PathBuf> {
+    if strategy == FitStrategy::Off {
+        return Ok(path.to_owned());
+    }
+    let cache_dir = state_dir()?.join(CACHE_DIR);
+    if !cache_dir.exists() {
+        create_dir_all(&cache_dir)?;
+    }
+    let fitted_path = cache_dir.join(format!(
+        "{}_{width}x{height}_{}.png",
+        ContentHashes::of(path)?,
+        strategy.name()
+    ));
+    if fitted_path.exists() {
+        // So that it isn't considered unused
+        File::options()
+            .write(true)
+            .open(&fitted_path)?
+            .set_modified(SystemTime::now())?;
+    } else {
+        fit(&image::open(path)?, width, height, strategy).save(&fitted_path)?;
+    }
+    Ok(fitted_path)
+}
+
+/// Removes the fitted images whose wallpaper isn't in the library of `wallpapers_dir` anymore, or
+/// that haven't been used for a long time (resolution of an old monitor, strategy not used
+/// anymore...).
+pub(crate) fn clean_cache(wallpapers_dir: &Path) -> DonResult<usize> {
+    let cache_dir = state_dir()?.join(CACHE_DIR);
+    if !cache_dir.exists() {
+        return Ok(0);
+    }
+    let content_hashes = ContentHashes::load(wallpapers_dir)?;
+    content_hashes.save()?;
+    let mut nb_removed = 0;
+    for entry in read_dir(cache_dir)? {
+        let path = entry?.path();
+        let source_hash = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.split('_').next())
+            .unwrap_or_default();
+        let unused = path.metadata()?.modified()?.elapsed().unwrap_or_default() > UNUSED_FOR;
+        if unused || content_hashes.find(source_hash).is_none() {
+            remove_file(path)?;
+            nb_removed += 1;
+        }
+    }
+    Ok(nb_removed)
+}
+
+fn smart_crop(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    strategy: FitStrategy,
+) -> DynamicImage {
+    let (image_width, image_height) = image.dimensions();
+    let scale = f64::max(
+        width as f64 / image_width as f64,
+        height as f64 / image_height as f64,
+    );
+    let (scaled_width, scaled_height) = (
+        ((image_width as f64 * scale).round() as u32).max(width),
+        ((image_height as f64 * scale).round() as u32).max(height),
+    );
+    let scaled = image.resize_exact(scaled_width, scaled_height, FilterType::Lanczos3);
+    if (scaled_width, scaled_height) == (width, height) {
+        return scaled;
+    }
+
+    // Only one axis overflows, the window slides along it
+    let horizontal = scaled_width > width;
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_luma8();
+    let (length, window) = match horizontal {
+        true => (
+            thumbnail.width(),
+            (thumbnail.width() as f64 * width as f64 / scaled_width as f64).round() as u32,
+        ),
+        false => (
+            thumbnail.height(),
+            (thumbnail.height() as f64 * height as f64 / scaled_height as f64).round() as u32,
+        ),
+    };
+    let best_offset = match strategy {
+        FitStrategy::EdgeCrop => best_edge_offset(&thumbnail, length, window, horizontal),
+        _ => best_entropy_offset(&thumbnail, length, window, horizontal),
+    };
+    let offset = |scaled_length: u32, target: u32| {
+        ((best_offset as f64 * scaled_length as f64 / length as f64).round() as u32)
+            .min(scaled_length - target)
+    };
+    match horizontal {
+        true => scaled.crop_imm(offset(scaled_width, width), 0, width, height),
+        false => scaled.crop_imm(0, offset(scaled_height, height), width, height),
+    }
+}
+
+/// Luminance of the pixel at `position` along the sliding axis and `across` the other one.
+fn pixel(thumbnail: &GrayImage, horizontal: bool, position: u32, across: u32) -> u8 {
+    match horizontal {
+        true => thumbnail.get_pixel(position, across)[0],
+        false => thumbnail.get_pixel(across, position)[0],
+    }
+}
+
+fn across_length(thumbnail: &GrayImage, horizontal: bool) -> u32 {
+    match horizontal {
+        true => thumbnail.height(),
+        false => thumbnail.width(),
+    }
+}
+
+/// Offset of the window with the most gradient energy.
+fn best_edge_offset(thumbnail: &GrayImage, length: u32, window: u32, horizontal: bool) -> u32 {
+    let across = across_length(thumbnail, horizontal);
+    let energies = (0..length)
+        .map(|position| {
+            (0..across)
+                .map(|other| {
+                    let value = pixel(thumbnail, horizontal, position, other) as i32;
+                    let next = |p: u32, o: u32| pixel(thumbnail, horizontal, p, o) as i32;
+                    let along = match position + 1 < length {
+                        true => (next(position + 1, other) - value).abs(),
+                        false => 0,
+                    };
+                    let orthogonal = match other + 1 < across {
+                        true => (next(position, other + 1) - value).abs(),
+                        false => 0,
+                    };
+                    (along + orthogonal) as u64
+                })
+                .sum::<u64>()
+        })
+        .collect::<Vec<_>>();
+    (0..=length - window)
+        .max_by_key(|&offset| {
+            energies[offset as usize..(offset + window) as usize]
+                .iter()
+                .sum::<u64>()
+        })
+        .unwrap_or_default()
+}
+
+/// Offset of the window whose luminance histogram has the highest entropy.
+fn best_entropy_offset(thumbnail: &GrayImage, length: u32, window: u32, horizontal: bool) -> u32 {
+    let across = across_length(thumbnail, horizontal);
+    let entropy = |offset: u32| {
+        let mut histogram = [0u32; 256];
+        for position in offset..offset + window {
+            for other in 0..across {
+                histogram[pixel(thumbnail, horizontal, position, other) as usize] += 1;
+            }
+        }
+        let total = (window * across) as f64;
+        histogram
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let probability = count as f64 / total;
+                -probability * probability.log2()
+            })
+            .sum::<f64>()
+    };
+    (0..=length - window)
+        .map(|offset| (offset, entropy(offset)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(offset, _)| offset)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use image::{ImageBuffer, Rgb};
+
+    /// Flat blue, with a noisy square centered at `x_ratio` of the width.
+    fn detail_at(x_ratio: f64) -> DynamicImage {
+        let (width, height) = (1200, 400);
+        let center = (width as f64 * x_ratio) as i64;
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+            if (x as i64 - center).abs() < 100 && (150..250).contains(&y) {
+                let noise = ((x * 7919 + y * 104729) % 256) as u8;
+                Rgb([noise, noise / 2, 255 - noise])
+            } else {
+                Rgb([30, 60, 200])
+            }
+        }))
+    }
+
+    /// Whether the crop contains the noisy square.
+    fn contains_detail(crop: &DynamicImage) -> bool {
+        crop.to_rgb8()
+            .pixels()
+            .any(|pixel| pixel != &Rgb([30, 60, 200]))
+    }
+
+    #[test]
+    fn test_strategies_give_exact_size() {
+        let image = detail_at(0.5);
+        for strategy in [
+            FitStrategy::Off,
+            FitStrategy::Fill,
+            FitStrategy::Fit,
+            FitStrategy::EntropyCrop,
+            FitStrategy::EdgeCrop,
+        ] {
+            assert_eq!(fit(&image, 300, 300, strategy).dimensions(), (300, 300));
+            assert_eq!(fit(&image, 900, 200, strategy).dimensions(), (900, 200));
+        }
+    }
+
+    #[test]
+    fn test_smart_crops_follow_details() {
+        let image = detail_at(0.85);
+        assert!(!contains_detail(&fit(&image, 400, 400, FitStrategy::Fill)));
+        assert!(contains_detail(&fit(
+            &image,
+            400,
+            400,
+            FitStrategy::EntropyCrop
+        )));
+        assert!(contains_detail(&fit(
+            &image,
+            400,
+            400,
+            FitStrategy::EdgeCrop
+        )));
+    }
+
+    #[test]
+    fn test_fit_adds_black_bars() {
+        let fitted = fit(&detail_at(0.5), 600, 600, FitStrategy::Fit).to_rgb8();
+        assert_eq!(fitted.get_pixel(300, 0), &Rgb([0, 0, 0]));
+        assert_eq!(fitted.get_pixel(10, 300), &Rgb([30, 60, 200]));
+    }
+}
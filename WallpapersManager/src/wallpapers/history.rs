@@ -0,0 +1,233 @@
+use crate::{
+    catalogue::{Catalogue, Wallpaper},
+    monitors::MonitorLayout,
+    state::{load_json, save_json},
+};
+
+use {
+    super::change::Images,
+    chrono::{DateTime, Local, Utc},
+    don_error::*,
+    serde::{Deserialize, Serialize},
+    std::path::{Path, PathBuf},
+};
+
+const HISTORY_FILE: &str = "history.json";
+
+/// How many changes of wallpapers are remembered.
+const HISTORY_LENGTH: usize = 200;
+
+/// Wallpapers displayed by each change, last ones last.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct History {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    pub(crate) displayed_at: DateTime<Utc>,
+    pub(crate) wallpapers: Vec<Shown>,
+}
+
+/// A wallpaper and the monitors it covered, by name as indexes change with the layout.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Shown {
+    pub(crate) monitors: Vec<String>,
+    pub(crate) path: PathBuf,
+}
+
+impl History {
+    pub(crate) fn load() -> DonResult<Self> {
+        load_json(HISTORY_FILE)
+    }
+
+    pub(crate) fn save(&self) -> DonResult<()> {
+        save_json(HISTORY_FILE, self)
+    }
+
+    pub(crate) fn record(&mut self, layout: &MonitorLayout, images: &Images, now: DateTime<Utc>) {
+        self.entries.push(Entry {
+            displayed_at: now,
+            wallpapers: images
+                .iter()
+                .map(|(group, path)| Shown {
+                    monitors: group
+                        .iter()
+                        .map(|&index| layout.monitors[index].name.clone())
+                        .collect(),
+                    path: path.clone(),
+                })
+                .collect(),
+        });
+        if self.entries.len() > HISTORY_LENGTH {
+            self.entries.remove(0);
+        }
+    }
+
+    pub(crate) fn current(&self) -> Option<&Entry> {
+        self.entries.last()
+    }
+
+    /// Forgets the current wallpapers, and returns the ones displayed before them.
+    pub(crate) fn go_back(&mut self, layout: &MonitorLayout) -> DonResult<Images> {
+        let [.., previous, _] = self.entries.as_slice() else {
+            bail!("No previous wallpapers");
+        };
+        let images = previous.images(layout)?;
+        self.entries.pop();
+        Ok(images)
+    }
+
+    /// What was displayed on the monitor named `monitor`, last first.
+    fn for_monitor<'h>(&'h self, monitor: &'h str) -> impl Iterator<Item = (&'h Entry, &'h Path)> {
+        self.entries.iter().rev().filter_map(move |entry| {
+            entry
+                .wallpapers
+                .iter()
+                .find(|shown| shown.monitors.iter().any(|name| name == monitor))
+                .map(|shown| (entry, shown.path.as_path()))
+        })
+    }
+}
+
+impl Entry {
+    fn images(&self, layout: &MonitorLayout) -> DonResult<Images> {
+        let mut images = vec![];
+        for shown in &self.wallpapers {
+            let mut group = vec![];
+            for name in &shown.monitors {
+                group.push(
+                    layout
+                        .monitors
+                        .iter()
+                        .position(|monitor| &monitor.name == name)
+                        .ok_or_don_err(format!("Monitor {name} isn't connected anymore"))?,
+                );
+            }
+            images.push((group, shown.path.clone()));
+        }
+        Ok(images)
+    }
+}
+
+/// Prints the wallpapers displayed on each monitor, where they come from and who made them.
+pub fn current() -> DonResult<()> {
+    let history = History::load()?;
+    let entry = history
+        .current()
+        .ok_or_don_err("No wallpapers displayed yet")?;
+    let catalogue = Catalogue::open()?;
+    for shown in &entry.wallpapers {
+        let wallpaper = catalogue.find(&shown.path)?;
+        let field = |get: fn(&Wallpaper) -> &Option<String>| {
+            wallpaper
+                .as_ref()
+                .and_then(|wallpaper| get(wallpaper).clone())
+        };
+        println!(
+            "{} : {}\n  {}",
+            shown.monitors.join(", "),
+            shown.path.display(),
+            field(|wallpaper| &wallpaper.source_url)
+                .as_deref()
+                .unwrap_or("No known source")
+        );
+        if let Some(author) = field(|wallpaper| &wallpaper.author) {
+            match field(|wallpaper| &wallpaper.licence) {
+                Some(licence) => println!("  By {author}, {licence}"),
+                None => println!("  By {author}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the last `limit` changes of wallpapers, or what was displayed on `monitor` only.
+pub fn print(monitor: Option<&str>, limit: usize) -> DonResult<()> {
+    let history = History::load()?;
+    let format = |entry: &Entry| {
+        entry
+            .displayed_at
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    };
+    match monitor {
+        Some(monitor) => {
+            for (entry, path) in history.for_monitor(monitor).take(limit) {
+                println!("{}  {}", format(entry), path.display());
+            }
+        }
+        None => {
+            for entry in history.entries.iter().rev().take(limit) {
+                println!("{}", format(entry));
+                for shown in &entry.wallpapers {
+                    println!("  {} : {}", shown.monitors.join(", "), shown.path.display());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::monitors::Monitor;
+
+    fn layout(names: &[&str]) -> MonitorLayout {
+        MonitorLayout {
+            monitors: names
+                .iter()
+                .enumerate()
+                .map(|(index, name)| Monitor::new(name, 1920, 1080, index as i32 * 1920, 0))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_go_back_by_monitor_name() {
+        let mut history = History::default();
+        let before = layout(&["DP-1", "DP-2"]);
+        history.record(
+            &before,
+            &vec![(vec![0, 1], PathBuf::from("dual.jpg"))],
+            Utc::now(),
+        );
+        history.record(
+            &before,
+            &vec![
+                (vec![0], PathBuf::from("forest.jpg")),
+                (vec![1], PathBuf::from("sea.jpg")),
+            ],
+            Utc::now(),
+        );
+        assert_eq!(
+            history
+                .for_monitor("DP-2")
+                .map(|(_, path)| path)
+                .collect::<Vec<_>>(),
+            [Path::new("sea.jpg"), Path::new("dual.jpg")]
+        );
+
+        // The monitors were swapped meanwhile
+        assert_eq!(
+            history.go_back(&layout(&["DP-2", "DP-1"])).unwrap(),
+            vec![(vec![1, 0], PathBuf::from("dual.jpg"))]
+        );
+        assert!(history.go_back(&before).is_err());
+        assert_eq!(history.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_go_back_to_disconnected_monitor() {
+        let mut history = History::default();
+        let images = vec![(vec![1], PathBuf::from("forest.jpg"))];
+        history.record(&layout(&["eDP-1", "HDMI-1"]), &images, Utc::now());
+        history.record(&layout(&["eDP-1", "HDMI-1"]), &images, Utc::now());
+        assert!(history.go_back(&layout(&["eDP-1"])).is_err());
+        // Nothing is forgotten when going back fails
+        assert_eq!(history.entries.len(), 2);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{
+    catalogue::Catalogue,
+    state::{load_json, save_json, state_dir},
+};
+
+use {
+    don_error::*,
+    serde::{Deserialize, Serialize},
+    std::{
+        fs::{remove_file, rename},
+        path::PathBuf,
+    },
+};
+
+const JOURNAL_FILE: &str = "sort_journal.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Move {
+    pub(crate) from: PathBuf,
+    pub(crate) to: PathBuf,
+}
+
+/// Moves of the last sort, written before any of them is applied so that an interrupted sort can
+/// still be reverted.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Journal {
+    pub(crate) moves: Vec<Move>,
+}
+
+impl Journal {
+    pub(crate) fn load() -> DonResult<Self> {
+        load_json(JOURNAL_FILE)
+    }
+
+    pub(crate) fn save(&self) -> DonResult<()> {
+        save_json(JOURNAL_FILE, self)
+    }
+
+    /// Forgets the journal, once reverted.
+    pub(crate) fn remove() -> DonResult<()> {
+        let path = state_dir()?.join(JOURNAL_FILE);
+        if path.exists() {
+            remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the files back, last move first. Moves that weren't applied, or whose file has been
+    /// moved or replaced since, are skipped. Returns the number of files moved back.
+    pub(crate) fn revert(&self, catalogue: &Catalogue) -> DonResult<usize> {
+        let mut nb_reverted = 0;
+        for Move { from, to } in self.moves.iter().rev() {
+            if !to.exists() || from.exists() {
+                continue;
+            }
+            rename(to, from)?;
+            catalogue.record_move(to, from)?;
+            nb_reverted += 1;
+        }
+        Ok(nb_reverted)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    #[test]
+    fn test_revert_skips_moves_not_applied() {
+        let dir = std::env::temp_dir().join(format!("{}_journal", std::process::id()));
+        create_dir_all(dir.join("single")).unwrap();
+        let catalogue = Catalogue::open_in_memory().unwrap();
+        let journal = Journal {
+            moves: vec![
+                Move {
+                    from: dir.join("forest.jpg"),
+                    to: dir.join("single/forest.jpg"),
+                },
+                // Interrupted before this one
+                Move {
+                    from: dir.join("sea.jpg"),
+                    to: dir.join("single/sea.jpg"),
+                },
+            ],
+        };
+        write(dir.join("single/forest.jpg"), "").unwrap();
+        write(dir.join("sea.jpg"), "").unwrap();
+
+        assert_eq!(journal.revert(&catalogue).unwrap(), 1);
+        assert!(dir.join("forest.jpg").exists());
+        assert!(!dir.join("single/forest.jpg").exists());
+        assert!(dir.join("sea.jpg").exists());
+        remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,2 +1,14 @@
+pub(crate) mod buckets;
 pub(crate) mod change;
+mod compose;
+pub(crate) mod content_hashes;
+pub(crate) mod dedup;
+mod file_cache;
+pub(crate) mod fit;
+pub(crate) mod history;
+mod journal;
+pub(crate) mod quarantine;
+pub(crate) mod ratings;
+mod selection;
 pub(crate) mod sort;
+pub(crate) mod watch;
@@ -0,0 +1,210 @@
+use crate::{
+    monitors::MonitorLayout,
+    state::{load_json, save_json},
+};
+
+use {
+    don_error::*,
+    serde::Deserialize,
+    std::{collections::BTreeMap, fmt, path::PathBuf},
+};
+
+const REPORT_FILE: &str = "quarantine.json";
+
+/// Reason why each quarantined wallpaper is kept out of the buckets.
+pub(crate) type Report = BTreeMap<PathBuf, String>;
+
+/// Where the wallpapers that would look bad on the monitors are moved during sort, instead of a
+/// bucket. In the config :
+///
+/// ```toml
+/// [quarantine]
+/// dir = "quarantine"
+/// min_resolution = 0.75
+/// keep = ["pixel_art.png"]
+/// ```
+#[derive(Debug, Deserialize)]
+pub(crate) struct Quarantine {
+    /// Relative to the wallpapers dir
+    #[serde(default = "default_dir")]
+    pub(crate) dir: String,
+    /// Fraction of the width and height of a monitor (or group of monitors for spanning
+    /// wallpapers) a wallpaper must have
+    #[serde(default = "default_min_resolution")]
+    pub(crate) min_resolution: f64,
+    /// File names of wallpapers that are never quarantined
+    #[serde(default)]
+    pub(crate) keep: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Reason {
+    Unreadable(String),
+    LowResolution {
+        width: u32,
+        height: u32,
+        monitor_width: u32,
+        monitor_height: u32,
+    },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Unreadable(err) => write!(f, "unreadable ({err})"),
+            Reason::LowResolution {
+                width,
+                height,
+                monitor_width,
+                monitor_height,
+            } => write!(
+                f,
+                "{width}x{height} is too small for {monitor_width}x{monitor_height}"
+            ),
+        }
+    }
+}
+
+impl Default for Quarantine {
+    fn default() -> Self {
+        Quarantine {
+            dir: default_dir(),
+            min_resolution: default_min_resolution(),
+            keep: vec![],
+        }
+    }
+}
+
+impl Quarantine {
+    pub(crate) fn keeps(&self, file_name: &str) -> bool {
+        self.keep.iter().any(|kept| kept == file_name)
+    }
+
+    /// Why a wallpaper of this size should be quarantined, if it should : when it's too small for
+    /// every one of the `targets` it could be displayed on.
+    pub(crate) fn check(&self, targets: &[(u32, u32)], width: u32, height: u32) -> Option<Reason> {
+        let big_enough = |&(target_width, target_height): &(u32, u32)| {
+            width as f64 >= target_width as f64 * self.min_resolution
+                && height as f64 >= target_height as f64 * self.min_resolution
+        };
+        if targets.is_empty() || targets.iter().any(big_enough) {
+            return None;
+        }
+        let &(monitor_width, monitor_height) = targets
+            .iter()
+            .min_by_key(|(target_width, target_height)| target_width * target_height)?;
+        Some(Reason::LowResolution {
+            width,
+            height,
+            monitor_width,
+            monitor_height,
+        })
+    }
+}
+
+/// Sizes wallpapers are displayed at, on a single monitor and spanning across monitors.
+pub(crate) struct Targets {
+    pub(crate) single: Vec<(u32, u32)>,
+    pub(crate) spanning: Vec<(u32, u32)>,
+}
+
+impl Targets {
+    pub(crate) fn new(layout: &MonitorLayout) -> Self {
+        Targets {
+            single: layout
+                .monitors
+                .iter()
+                .map(|monitor| (monitor.width, monitor.height))
+                .collect(),
+            spanning: layout
+                .spanning_groups()
+                .iter()
+                .map(|group| {
+                    let bounds = layout.bounds(group);
+                    (bounds.width, bounds.height)
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn for_bucket(&self, spanning: bool) -> &[(u32, u32)] {
+        match spanning {
+            true => &self.spanning,
+            false => &self.single,
+        }
+    }
+}
+
+pub(crate) fn load_report() -> DonResult<Report> {
+    load_json(REPORT_FILE)
+}
+
+pub(crate) fn save_report(report: &Report) -> DonResult<()> {
+    save_json(REPORT_FILE, report)
+}
+
+/// Prints the quarantined wallpapers of the last sort, and why they were quarantined.
+pub fn report() -> DonResult<()> {
+    let report = load_report()?;
+    if report.is_empty() {
+        println!("No wallpaper in quarantine");
+    }
+    for (path, reason) in report {
+        println!("{} : {reason}", path.display());
+    }
+    Ok(())
+}
+
+fn default_dir() -> String {
+    "quarantine".to_string()
+}
+
+fn default_min_resolution() -> f64 {
+    0.75
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::monitors::Monitor;
+
+    #[test]
+    fn test_check_against_most_favourable_monitor() {
+        let quarantine = Quarantine::default();
+        let targets = Targets::new(&MonitorLayout {
+            monitors: vec![
+                Monitor::new("eDP-1", 1920, 1080, 0, 0),
+                Monitor::new("DP-1", 3840, 2160, 1920, 0),
+            ],
+        });
+        // Too small for the 4K monitor but fine on the laptop
+        assert_eq!(quarantine.check(targets.for_bucket(false), 1600, 900), None);
+        assert_eq!(
+            quarantine.check(targets.for_bucket(false), 800, 600),
+            Some(Reason::LowResolution {
+                width: 800,
+                height: 600,
+                monitor_width: 1920,
+                monitor_height: 1080,
+            })
+        );
+        // Spanning across both
+        assert_eq!(targets.for_bucket(true), &[(5760, 2160)]);
+        assert!(quarantine
+            .check(targets.for_bucket(true), 3840, 1080)
+            .is_some());
+        assert_eq!(quarantine.check(targets.for_bucket(true), 5120, 1620), None);
+    }
+
+    #[test]
+    fn test_nothing_to_compare_to() {
+        let targets = Targets::new(&MonitorLayout {
+            monitors: vec![Monitor::new("eDP-1", 1920, 1080, 0, 0)],
+        });
+        assert_eq!(
+            Quarantine::default().check(targets.for_bucket(true), 10, 10),
+            None
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::catalogue::{Catalogue, Rating};
+
+use {
+    super::history::{Entry, History},
+    don_error::*,
+    std::path::{absolute, Path, PathBuf},
+};
+
+/// Stars of the wallpapers that aren't rated.
+const DEFAULT_STARS: u8 = 3;
+
+/// How many times a wallpaper is displayed per cycle of its shuffle bag : as many as its stars,
+/// twice that for favourites, and never when banned.
+pub(crate) fn weight(rating: Option<&Rating>) -> u32 {
+    let rating = rating.cloned().unwrap_or_default();
+    if rating.banned {
+        return 0;
+    }
+    let stars = rating.stars.unwrap_or(DEFAULT_STARS) as u32;
+    match rating.favourite {
+        true => stars * 2,
+        false => stars,
+    }
+}
+
+/// The wallpapers given on the command line, or else the ones currently displayed, on `monitor`
+/// only if set.
+fn targets(paths: &[PathBuf], monitor: Option<&str>) -> DonResult<Vec<PathBuf>> {
+    if !paths.is_empty() {
+        return paths
+            .iter()
+            .map(|path| match path.is_file() {
+                true => Ok(absolute(path)?),
+                false => bail!("{path:?} is not a file"),
+            })
+            .collect();
+    }
+    let history = History::load()?;
+    let current = history
+        .current()
+        .ok_or_don_err("No wallpapers displayed yet")?;
+    displayed_on(current, monitor)
+}
+
+fn displayed_on(current: &Entry, monitor: Option<&str>) -> DonResult<Vec<PathBuf>> {
+    let paths = current
+        .wallpapers
+        .iter()
+        .filter(|shown| monitor.is_none_or(|monitor| shown.monitors.iter().any(|m| m == monitor)))
+        .map(|shown| shown.path.clone())
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        bail!("Nothing is displayed on {}", monitor.unwrap_or_default());
+    }
+    Ok(paths)
+}
+
+fn for_each_target(
+    paths: &[PathBuf],
+    monitor: Option<&str>,
+    mut apply: impl FnMut(&Catalogue, &Path) -> DonResult<String>,
+) -> DonResult<()> {
+    let catalogue = Catalogue::open()?;
+    for path in targets(paths, monitor)? {
+        let done = apply(&catalogue, &path)?;
+        println!("{} : {done}", path.display());
+    }
+    Ok(())
+}
+
+/// Adds `tags` to the wallpapers, or removes them.
+pub fn tag(
+    tags: &[String],
+    remove: bool,
+    paths: &[PathBuf],
+    monitor: Option<&str>,
+) -> DonResult<()> {
+    for_each_target(paths, monitor, |catalogue, path| {
+        match remove {
+            true => catalogue.remove_tags(path, tags)?,
+            false => catalogue.add_tags(path, tags)?,
+        }
+        Ok(format!("tagged {}", catalogue.tags(path)?.join(", ")))
+    })
+}
+
+/// Gives the wallpapers from 1 to 5 stars, or forgets their rating with 0.
+pub fn rate(stars: u8, paths: &[PathBuf], monitor: Option<&str>) -> DonResult<()> {
+    if stars > 5 {
+        bail!("Wallpapers are rated from 1 to 5 stars");
+    }
+    for_each_target(paths, monitor, |catalogue, path| {
+        catalogue.update_rating(path, |rating| rating.stars = (stars > 0).then_some(stars))?;
+        Ok(match stars {
+            0 => "not rated anymore".to_string(),
+            stars => format!("rated {stars}/5"),
+        })
+    })
+}
+
+/// Prevents the wallpapers from being displayed again, or allows it back with `remove`.
+pub fn ban(remove: bool, paths: &[PathBuf], monitor: Option<&str>) -> DonResult<()> {
+    for_each_target(paths, monitor, |catalogue, path| {
+        catalogue.update_rating(path, |rating| rating.banned = !remove)?;
+        Ok(match remove {
+            true => "not banned anymore",
+            false => "banned, it won't be displayed again",
+        }
+        .to_string())
+    })
+}
+
+/// Displays the wallpapers twice as often, or like the others again with `remove`.
+pub fn favourite(remove: bool, paths: &[PathBuf], monitor: Option<&str>) -> DonResult<()> {
+    for_each_target(paths, monitor, |catalogue, path| {
+        catalogue.update_rating(path, |rating| rating.favourite = !remove)?;
+        Ok(match remove {
+            true => "not a favourite anymore",
+            false => "favourite",
+        }
+        .to_string())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use {super::super::history::Shown, chrono::Utc};
+
+    #[test]
+    fn test_weight() {
+        let rating = |stars, favourite, banned| Rating {
+            stars,
+            favourite,
+            banned,
+        };
+        assert_eq!(weight(None), 3);
+        assert_eq!(weight(Some(&rating(Some(1), false, false))), 1);
+        assert_eq!(weight(Some(&rating(None, true, false))), 6);
+        assert_eq!(weight(Some(&rating(Some(5), true, false))), 10);
+        assert_eq!(weight(Some(&rating(Some(5), true, true))), 0);
+    }
+
+    #[test]
+    fn test_displayed_on_monitor() {
+        let current = Entry {
+            displayed_at: Utc::now(),
+            wallpapers: vec![
+                Shown {
+                    monitors: vec!["DP-1".to_string(), "DP-2".to_string()],
+                    path: PathBuf::from("dual.jpg"),
+                },
+                Shown {
+                    monitors: vec!["HDMI-1".to_string()],
+                    path: PathBuf::from("single.jpg"),
+                },
+            ],
+        };
+        assert_eq!(displayed_on(&current, None).unwrap().len(), 2);
+        assert_eq!(
+            displayed_on(&current, Some("DP-2")).unwrap(),
+            [PathBuf::from("dual.jpg")]
+        );
+        assert!(displayed_on(&current, Some("eDP-1")).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use crate::state::{load_json, save_json};
+
+use {
+    don_error::*,
+    rand::{seq::SliceRandom, Rng},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{HashMap, HashSet},
+        path::{Path, PathBuf},
+    },
+};
+
+const STATE_FILE: &str = "shuffle_bags.json";
+
+/// One shuffle bag per directory wallpapers are picked from, kept across runs.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct ShuffleBags {
+    bags: HashMap<PathBuf, ShuffleBag>,
+}
+
+impl ShuffleBags {
+    pub(crate) fn load() -> DonResult<Self> {
+        load_json(STATE_FILE)
+    }
+
+    pub(crate) fn save(&self) -> DonResult<()> {
+        save_json(STATE_FILE, self)
+    }
+
+    pub(crate) fn bag(&mut self, dir: &Path) -> &mut ShuffleBag {
+        self.bags.entry(dir.to_owned()).or_default()
+    }
+}
+
+/// Every wallpaper gets as many tickets per cycle as its weight, and a ticket is drawn each time a
+/// wallpaper is needed. Once all tickets are drawn, a new cycle starts. So every wallpaper is shown
+/// once per cycle (or `weight` times), instead of some never being shown.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct ShuffleBag {
+    /// Tickets drawn during the current cycle
+    drawn: HashMap<PathBuf, u32>,
+}
+
+impl ShuffleBag {
+    /// Draws `count` different wallpapers out of `candidates`, avoiding the `recent` ones unless
+    /// there is nothing else left. A weight of 0 means the wallpaper is never picked.
+    pub(crate) fn pick(
+        &mut self,
+        candidates: &[PathBuf],
+        count: usize,
+        recent: &[PathBuf],
+        weight: impl Fn(&Path) -> u32,
+        rng: &mut impl Rng,
+    ) -> DonResult<Vec<PathBuf>> {
+        // Files removed from the library
+        let candidate_set = candidates.iter().collect::<HashSet<_>>();
+        self.drawn.retain(|path, _| candidate_set.contains(path));
+        let recent = recent.iter().collect::<HashSet<_>>();
+
+        let mut picked: Vec<PathBuf> = vec![];
+        while picked.len() < count {
+            let mut remaining = self.remaining_tickets(candidates, &weight, &picked);
+            if remaining.is_empty() {
+                self.drawn.clear();
+                remaining = self.remaining_tickets(candidates, &weight, &picked);
+            }
+            if remaining.is_empty() {
+                bail!(
+                    "Not enough wallpapers to pick {count} out of {} files",
+                    candidates.len()
+                );
+            }
+            let not_recent = remaining
+                .iter()
+                .filter(|(path, _)| !recent.contains(path))
+                .copied()
+                .collect::<Vec<_>>();
+            let pool = if not_recent.is_empty() {
+                remaining
+            } else {
+                not_recent
+            };
+            let (path, _) = pool
+                .choose_weighted(rng, |(_, tickets)| *tickets)
+                .map_err(|err| err_msg!("{err}"))?;
+            let path = (*path).clone();
+            *self.drawn.entry(path.clone()).or_default() += 1;
+            picked.push(path);
+        }
+        Ok(picked)
+    }
+
+    fn remaining_tickets<'c>(
+        &self,
+        candidates: &'c [PathBuf],
+        weight: &impl Fn(&Path) -> u32,
+        picked: &[PathBuf],
+    ) -> Vec<(&'c PathBuf, u32)> {
+        let picked = picked.iter().collect::<HashSet<_>>();
+        candidates
+            .iter()
+            .filter(|path| !picked.contains(path))
+            .map(|path| {
+                let drawn = self.drawn.get(path).copied().unwrap_or_default();
+                (path, weight(path).saturating_sub(drawn))
+            })
+            .filter(|(_, tickets)| *tickets > 0)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn wallpapers(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_each_wallpaper_shown_once_per_cycle() {
+        let candidates = wallpapers(&["a.jpg", "b.jpg", "c.jpg", "d.jpg"]);
+        let mut bag = ShuffleBag::default();
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..3 {
+            let mut cycle = vec![];
+            for _ in 0..2 {
+                cycle.extend(bag.pick(&candidates, 2, &[], |_| 1, &mut rng).unwrap());
+            }
+            cycle.sort();
+            assert_eq!(cycle, candidates);
+        }
+    }
+
+    #[test]
+    fn test_recent_wallpapers_avoided() {
+        let candidates = wallpapers(&["a.jpg", "b.jpg", "c.jpg"]);
+        let recent = wallpapers(&["a.jpg", "b.jpg"]);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..10 {
+            let picked = ShuffleBag::default()
+                .pick(&candidates, 1, &recent, |_| 1, &mut rng)
+                .unwrap();
+            assert_eq!(picked, wallpapers(&["c.jpg"]));
+        }
+        // Unless there is nothing else left
+        let picked = ShuffleBag::default()
+            .pick(&candidates, 3, &recent, |_| 1, &mut rng)
+            .unwrap();
+        assert_eq!(picked.len(), 3);
+    }
+
+    #[test]
+    fn test_weights_are_tickets_per_cycle() {
+        let candidates = wallpapers(&["banned.jpg", "favourite.jpg", "normal.jpg"]);
+        let weight = |path: &Path| match path.to_str() {
+            Some("banned.jpg") => 0,
+            Some("favourite.jpg") => 3,
+            _ => 1,
+        };
+        let mut bag = ShuffleBag::default();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut cycle = vec![];
+        for _ in 0..4 {
+            cycle.extend(bag.pick(&candidates, 1, &[], weight, &mut rng).unwrap());
+        }
+        cycle.sort();
+        assert_eq!(
+            cycle,
+            wallpapers(&[
+                "favourite.jpg",
+                "favourite.jpg",
+                "favourite.jpg",
+                "normal.jpg"
+            ])
+        );
+        assert!(ShuffleBag::default()
+            .pick(&candidates, 3, &[], weight, &mut rng)
+            .is_err());
+    }
+}
@@ -1,54 +1,264 @@
-use crate::CONFIG;
+use crate::{catalogue::Catalogue, monitors::MonitorLayout, state::lock, CONFIG};
 
 use {
+    super::{
+        buckets::{classify, Bucket},
+        fit::clean_cache,
+        journal::{Journal, Move},
+        quarantine::{load_report, save_report, Quarantine, Reason, Report, Targets},
+    },
     don_error::*,
     imagesize::size,
     std::{
+        collections::HashSet,
         fs::{create_dir_all, rename},
         path::{Path, PathBuf},
     },
     walkdir::WalkDir,
 };
 
-const RATIO_LIMIT: f64 = 16.0 / 9.0 * 1.3;
+/// Held while sorting, as the watcher and `wall download` may sort at the same time.
+const LOCK_FILE: &str = "sort.lock";
+
+/// What sort does with a file.
+enum Action {
+    Sort {
+        bucket: String,
+        width: u32,
+        height: u32,
+    },
+    Quarantine(String),
+}
+
+struct Planned {
+    from: PathBuf,
+    to: PathBuf,
+    action: Action,
+}
 
-pub fn perform(force_sort_all_wallpapers: bool) -> DonResult<()> {
+/// Everything that decides where the files of the library go.
+struct Library<'a> {
+    wallpapers_path: PathBuf,
+    buckets: Vec<Bucket>,
+    quarantine: &'a Quarantine,
+    /// None when the monitors couldn't be detected
+    targets: Option<Targets>,
+    previous_report: Report,
+}
+
+/// Sorts the wallpapers into their bucket, or the quarantine. With `force_sort_all_wallpapers`,
+/// the files already in a bucket are sorted again, for instance after the buckets changed. With
+/// `dry_run`, only prints the moves. Otherwise the moves are written to a journal before being
+/// applied, for `undo` to revert them. Waits for any other sort to end first.
+pub fn perform(force_sort_all_wallpapers: bool, dry_run: bool) -> DonResult<()> {
     let wallpapers_path = PathBuf::from(&CONFIG.wallpapers_dir);
     if !wallpapers_path.exists() {
         bail!("{} not found on this computer", &CONFIG.wallpapers_dir);
     }
-    let single_dir = wallpapers_path.join(&CONFIG.single_screen_dir);
-    if !single_dir.exists() {
-        create_dir_all(&single_dir)?;
+    let _lock = lock(LOCK_FILE)?;
+    let targets = match MonitorLayout::detect() {
+        Ok(layout) => Some(Targets::new(&layout)),
+        Err(err) => {
+            println!("Monitors not detected, low resolution wallpapers aren't quarantined : {err}");
+            None
+        }
+    };
+    let library = Library {
+        wallpapers_path,
+        buckets: CONFIG.buckets(),
+        quarantine: &CONFIG.quarantine,
+        targets,
+        previous_report: load_report()?,
+    };
+    let planned = library.plan(force_sort_all_wallpapers);
+    let moves = planned
+        .iter()
+        .filter(|planned| planned.from != planned.to)
+        .map(|planned| Move {
+            from: planned.from.clone(),
+            to: planned.to.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    if dry_run {
+        for planned in planned.iter().filter(|planned| planned.from != planned.to) {
+            match &planned.action {
+                Action::Sort { .. } => println!("{:?} -> {:?}", planned.from, planned.to),
+                Action::Quarantine(reason) => {
+                    println!("{:?} -> {:?} : {reason}", planned.from, planned.to)
+                }
+            }
+        }
+        println!("{} file(s) would be moved", moves.len());
+        return Ok(());
+    }
+
+    for bucket in &library.buckets {
+        let bucket_dir = bucket.dir(&library.wallpapers_path);
+        if !bucket_dir.exists() {
+            create_dir_all(bucket_dir)?;
+        }
+    }
+    // A sort without any move keeps the journal of the last one to undo
+    if !moves.is_empty() {
+        Journal { moves }.save()?;
+    }
+    let catalogue = Catalogue::open()?;
+    let mut report = Report::new();
+    for Planned { from, to, action } in planned {
+        if from != to {
+            apply(&from, &to).map_err(|err| {
+                err_msg!("{err}, `wall sort --undo` moves back the files already moved")
+            })?;
+            catalogue.record_move(&from, &to)?;
+        }
+        match action {
+            Action::Sort {
+                bucket,
+                width,
+                height,
+            } => catalogue.record_sorted(&to, width, height, &bucket)?,
+            Action::Quarantine(reason) => {
+                if from != to {
+                    println!("Quarantined {from:?} : {reason}");
+                }
+                report.insert(to, reason);
+            }
+        }
     }
-    let dual_dir: PathBuf = wallpapers_path.join(&CONFIG.dual_screen_dir);
-    if !dual_dir.exists() {
-        create_dir_all(&dual_dir)?;
+    if !report.is_empty() {
+        println!(
+            "{} wallpaper(s) in quarantine, see `wall quarantine`",
+            report.len()
+        );
     }
+    save_report(&report)?;
 
-    if force_sort_all_wallpapers {
-        move_all_files(&single_dir, &wallpapers_path)?;
-        move_all_files(&dual_dir, &wallpapers_path)?;
+    let nb_removed = clean_cache(&library.wallpapers_path)?;
+    if nb_removed > 0 {
+        println!("{nb_removed} stale fitted image(s) removed");
     }
 
-    get_file_paths(&wallpapers_path)
-        .filter(|img_path| !img_path.ends_with("Thumbs.db"))
-        .for_each(|img_path| {
-            try_or_report(|| {
-                let img_dimensions = size(&img_path)
-                    .map_err(|err| err_msg!("Problem with img {img_path:#?} : {err:#?}"))?;
-                if img_dimensions.width as f64 / img_dimensions.height as f64 <= RATIO_LIMIT {
-                    move_to(&img_path, &single_dir)?;
-                } else {
-                    move_to(&img_path, &dual_dir)?;
-                };
-                Ok(())
-            })
-        });
+    Ok(())
+}
 
+/// Moves back the files moved by the last sort.
+pub fn undo() -> DonResult<()> {
+    let _lock = lock(LOCK_FILE)?;
+    let journal = Journal::load()?;
+    if journal.moves.is_empty() {
+        println!("No sort to undo");
+        return Ok(());
+    }
+    let nb_reverted = journal.revert(&Catalogue::open()?)?;
+    Journal::remove()?;
+    println!(
+        "{nb_reverted} of {} file(s) moved back",
+        journal.moves.len()
+    );
     Ok(())
 }
 
+impl Library<'_> {
+    /// Where each file goes. Files are never planned to overwrite another one.
+    fn plan(&self, force_sort_all_wallpapers: bool) -> Vec<Planned> {
+        let bucket_dirs = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.dir(&self.wallpapers_path))
+            .collect::<Vec<_>>();
+        let mut taken = HashSet::new();
+        let mut planned = vec![];
+        for path in get_wallpaper_paths(&self.wallpapers_path) {
+            if !force_sort_all_wallpapers && bucket_dirs.iter().any(|dir| path.starts_with(dir)) {
+                continue;
+            }
+            match self.plan_file(&path) {
+                Ok(Some((dir, action))) => {
+                    let to = destination(&path, &dir, &taken);
+                    taken.insert(to.clone());
+                    planned.push(Planned {
+                        from: path,
+                        to,
+                        action,
+                    });
+                }
+                Ok(None) => (),
+                Err(err) => err.report(),
+            }
+        }
+        planned
+    }
+
+    /// The dir a file goes to, and why. None when no bucket accepts it.
+    fn plan_file(&self, path: &Path) -> DonResult<Option<(PathBuf, Action)>> {
+        let quarantine = |reason: String| {
+            Ok(Some((
+                self.wallpapers_path.join(&self.quarantine.dir),
+                Action::Quarantine(reason),
+            )))
+        };
+        let kept = self
+            .quarantine
+            .keeps(&path.file_name().unwrap_or_default().to_string_lossy());
+        if let (None, Some(reason), false) = (&self.targets, self.previous_report.get(path), kept) {
+            // Without the monitors, what was quarantined stays there
+            return quarantine(reason.clone());
+        }
+        let img_dimensions = match size(path) {
+            Ok(img_dimensions) => img_dimensions,
+            Err(err) if !kept => {
+                return quarantine(Reason::Unreadable(err.to_string()).to_string())
+            }
+            Err(err) => bail!("Problem with img {path:#?} : {err:#?}"),
+        };
+        let (width, height) = (img_dimensions.width as u32, img_dimensions.height as u32);
+        let Some(bucket) = classify(&self.buckets, width, height) else {
+            println!("No bucket for {path:?} ({width}x{height})");
+            return Ok(None);
+        };
+        let low_resolution = self.targets.as_ref().and_then(|targets| {
+            self.quarantine
+                .check(targets.for_bucket(bucket.spanning), width, height)
+        });
+        if let (Some(reason), false) = (low_resolution, kept) {
+            return quarantine(reason.to_string());
+        }
+        Ok(Some((
+            bucket.dir(&self.wallpapers_path),
+            Action::Sort {
+                bucket: bucket.name.clone(),
+                width,
+                height,
+            },
+        )))
+    }
+}
+
+/// Moves a file, and creates the dir it's moved to if needed.
+fn apply(from: &Path, to: &Path) -> DonResult<()> {
+    if to.exists() {
+        bail!("Moving {from:?} would overwrite {to:?}");
+    }
+    let dir = to.parent().expect("Destinations are in a bucket dir");
+    if !dir.exists() {
+        create_dir_all(dir)?;
+    }
+    rename(from, to).map_err(|err| err_msg!("Moving {from:?} to {to:?} failed : {err}"))
+}
+
+/// Files of `dir` and its sub directories that are wallpapers.
+pub(crate) fn get_wallpaper_paths(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    get_file_paths(dir)
+        .filter(|img_path| !img_path.ends_with("Thumbs.db"))
+        // Downloads still in progress
+        .filter(|img_path| {
+            img_path
+                .extension()
+                .is_none_or(|extension| extension != "part")
+        })
+}
+
 fn get_file_paths(dir: &Path) -> impl Iterator<Item = PathBuf> {
     WalkDir::new(dir).into_iter().filter_map(|e| {
         e.ok()
@@ -56,20 +266,125 @@ fn get_file_paths(dir: &Path) -> impl Iterator<Item = PathBuf> {
     })
 }
 
-fn move_to(file_path: &Path, new_dir: &Path) -> Result<(), std::io::Error> {
-    rename(
-        file_path,
-        new_dir.join(
+/// Where a file moved to `dir` ends up, without overwriting an existing file nor one of the
+/// `taken` paths.
+fn destination(file_path: &Path, dir: &Path, taken: &HashSet<PathBuf>) -> PathBuf {
+    if file_path.parent() == Some(dir) {
+        return file_path.to_owned();
+    }
+    available_path_with(
+        dir,
+        Path::new(
             file_path
                 .file_name()
                 .expect("images all have valid filename"),
         ),
+        |path| path.exists() || taken.contains(path),
     )
 }
 
-fn move_all_files(old_dir: &Path, new_dir: &Path) -> DonResult<()> {
-    for img_path in get_file_paths(old_dir) {
-        move_to(&img_path, new_dir)?;
+/// First path of the form `dir/name.ext`, `dir/name_1.ext`, `dir/name_2.ext`... that doesn't
+/// exist yet, so that a wallpaper never overwrites another one.
+pub(crate) fn available_path(dir: &Path, file_name: &Path) -> PathBuf {
+    available_path_with(dir, file_name, |path| path.exists())
+}
+
+fn available_path_with(dir: &Path, file_name: &Path, is_taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let path = dir.join(file_name);
+    if !is_taken(&path) {
+        return path;
+    }
+    let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+    (1..)
+        .map(|suffix| match file_name.extension() {
+            Some(extension) => dir.join(format!("{stem}_{suffix}.{}", extension.to_string_lossy())),
+            None => dir.join(format!("{stem}_{suffix}")),
+        })
+        .find(|path| !is_taken(path))
+        .expect("There is a finite number of files in a directory")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use {
+        image::RgbImage,
+        std::fs::{remove_dir_all, write},
+    };
+
+    #[test]
+    fn test_available_path_adds_numeric_suffix() {
+        let dir = std::env::temp_dir().join(format!("{}_available_path", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let forest = Path::new("forest.jpg");
+        assert_eq!(available_path(&dir, forest), dir.join("forest.jpg"));
+        write(dir.join("forest.jpg"), "").unwrap();
+        assert_eq!(available_path(&dir, forest), dir.join("forest_1.jpg"));
+        write(dir.join("forest_1.jpg"), "").unwrap();
+        assert_eq!(available_path(&dir, forest), dir.join("forest_2.jpg"));
+        // Another extension doesn't collide
+        assert_eq!(
+            available_path(&dir, Path::new("forest.png")),
+            dir.join("forest.png")
+        );
+        write(dir.join("sea.side"), "").unwrap();
+        assert_eq!(
+            available_path(&dir, Path::new("sea.side")),
+            dir.join("sea_1.side")
+        );
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_plan_never_overwrites() {
+        let dir = std::env::temp_dir().join(format!("{}_plan", std::process::id()));
+        for sub_dir in ["single", "old"] {
+            create_dir_all(dir.join(sub_dir)).unwrap();
+        }
+        let save = |path: &str, width, height| {
+            RgbImage::new(width, height).save(dir.join(path)).unwrap();
+        };
+        save("forest.png", 40, 30);
+        save("old/forest.png", 40, 30);
+        save("single/forest.png", 40, 30);
+        save("wide.png", 400, 100);
+        let quarantine = Quarantine::default();
+        let library = Library {
+            wallpapers_path: dir.clone(),
+            buckets: Bucket::defaults("single", "dual"),
+            quarantine: &quarantine,
+            targets: None,
+            previous_report: Report::new(),
+        };
+        let moves = |force| {
+            let mut moves = library
+                .plan(force)
+                .into_iter()
+                .map(|planned| (planned.from, planned.to))
+                .collect::<Vec<_>>();
+            moves.sort();
+            moves
+        };
+
+        let forest_moves = [
+            (dir.join("forest.png"), dir.join("single/forest_1.png")),
+            (dir.join("old/forest.png"), dir.join("single/forest_2.png")),
+        ];
+        let wide_move = (dir.join("wide.png"), dir.join("dual/wide.png"));
+        assert_eq!(
+            moves(false),
+            [
+                forest_moves[0].clone(),
+                forest_moves[1].clone(),
+                wide_move.clone()
+            ]
+        );
+        // The sorted file stays where it is
+        let already_sorted = (dir.join("single/forest.png"), dir.join("single/forest.png"));
+        assert!(moves(true).contains(&already_sorted));
+        // Nothing was moved
+        assert!(dir.join("wide.png").exists());
+        remove_dir_all(dir).unwrap();
     }
-    Ok(())
 }
@@ -0,0 +1,167 @@
+use crate::CONFIG;
+
+use {
+    super::sort,
+    don_error::*,
+    notify::{EventKind, RecursiveMode, Watcher},
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::mpsc::{channel, RecvTimeoutError},
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+/// How long a file must go without being written to before it's sorted.
+const QUIET_PERIOD: Duration = Duration::from_secs(3);
+
+/// Sorts the files dropped into the wallpapers dir once they're fully written. Only returns on
+/// error.
+pub fn perform() -> DonResult<()> {
+    let wallpapers_path = PathBuf::from(&CONFIG.wallpapers_dir);
+    if !wallpapers_path.exists() {
+        bail!("{} not found on this computer", &CONFIG.wallpapers_dir);
+    }
+    // Where sort moves files to, so changes there are its own
+    let sorted_dirs = CONFIG
+        .buckets()
+        .iter()
+        .map(|bucket| bucket.dir(&wallpapers_path))
+        .chain([wallpapers_path.join(&CONFIG.quarantine.dir)])
+        .collect::<Vec<_>>();
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&wallpapers_path, RecursiveMode::Recursive)?;
+    // Files dropped while nothing was watching
+    try_or_report(|| sort::perform(false, false));
+
+    let mut debouncer = Debouncer::new(QUIET_PERIOD);
+    loop {
+        let event = match debouncer.next_deadline() {
+            Some(deadline) => {
+                receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match event {
+            Ok(event) => {
+                let event = event?;
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    for path in event.paths {
+                        if is_new_wallpaper(&path, &sorted_dirs) {
+                            debouncer.touch(path, Instant::now());
+                        }
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => bail!("Stopped watching {wallpapers_path:?}"),
+        }
+        // Renamed or deleted files show up too
+        let settled = debouncer.settled(Instant::now());
+        // Waits for the sort of `wall download` when it's running one
+        if settled.iter().any(|path| path.is_file()) {
+            try_or_report(|| sort::perform(false, false));
+        }
+    }
+}
+
+/// Watches the wallpapers dir in the background, for the cron loop.
+pub(crate) fn spawn() {
+    thread::spawn(|| try_or_report(perform));
+}
+
+fn is_new_wallpaper(path: &Path, sorted_dirs: &[PathBuf]) -> bool {
+    !sorted_dirs.iter().any(|dir| path.starts_with(dir))
+        && !path.ends_with("Thumbs.db")
+        // Downloads still in progress
+        && path.extension().is_none_or(|extension| extension != "part")
+}
+
+/// Files written to recently, until they go quiet.
+struct Debouncer {
+    quiet_period: Duration,
+    last_written: HashMap<PathBuf, Instant>,
+}
+
+impl Debouncer {
+    fn new(quiet_period: Duration) -> Self {
+        Debouncer {
+            quiet_period,
+            last_written: HashMap::new(),
+        }
+    }
+
+    fn touch(&mut self, path: PathBuf, now: Instant) {
+        self.last_written.insert(path, now);
+    }
+
+    /// When the next file goes quiet, if any is being written.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.last_written
+            .values()
+            .min()
+            .map(|&last_written| last_written + self.quiet_period)
+    }
+
+    /// Forgets and returns the files that went quiet.
+    fn settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        let quiet_period = self.quiet_period;
+        let mut settled = vec![];
+        self.last_written.retain(|path, &mut last_written| {
+            let quiet = now.duration_since(last_written) >= quiet_period;
+            if quiet {
+                settled.push(path.clone());
+            }
+            !quiet
+        });
+        settled
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_debouncer_waits_for_writes_to_stop() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut debouncer = Debouncer::new(3 * second);
+        assert_eq!(debouncer.next_deadline(), None);
+
+        debouncer.touch(PathBuf::from("forest.jpg"), start);
+        debouncer.touch(PathBuf::from("sea.jpg"), start + second);
+        // Still being written
+        debouncer.touch(PathBuf::from("forest.jpg"), start + 2 * second);
+        assert_eq!(debouncer.next_deadline(), Some(start + 4 * second));
+        assert!(debouncer.settled(start + 3 * second).is_empty());
+        assert_eq!(
+            debouncer.settled(start + 4 * second),
+            vec![PathBuf::from("sea.jpg")]
+        );
+        assert_eq!(
+            debouncer.settled(start + 5 * second),
+            vec![PathBuf::from("forest.jpg")]
+        );
+        assert_eq!(debouncer.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_is_new_wallpaper() {
+        let sorted_dirs = [PathBuf::from("/wallpapers/single")];
+        assert!(is_new_wallpaper(
+            Path::new("/wallpapers/forest.jpg"),
+            &sorted_dirs
+        ));
+        assert!(!is_new_wallpaper(
+            Path::new("/wallpapers/single/forest.jpg"),
+            &sorted_dirs
+        ));
+        assert!(!is_new_wallpaper(
+            Path::new("/wallpapers/forest.jpg.part"),
+            &sorted_dirs
+        ));
+    }
+}
@@ -83,6 +83,16 @@ impl Client {
         )
     }
 
+    pub fn get_or_create_sub_folder(&self, parent: &Folder, title: &str) -> DonResult<Folder> {
+        match parent.sub_folders().find(|folder| folder.title == title) {
+            Some(folder) => Ok(folder.clone()),
+            None => self.create_folder(&CreateFolderInput {
+                title,
+                parent_id: &parent.id,
+            }),
+        }
+    }
+
     pub fn delete_bookmark(&self, bookmark_id: &BookmarkId) -> DonResult<()> {
         self.try_command(&["bookmarks", "delete", bookmark_id])
     }
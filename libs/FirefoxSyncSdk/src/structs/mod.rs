@@ -10,14 +10,14 @@ use {
     },
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum BookmarkOrFolder {
     Bookmark(Bookmark),
     Folder(Folder),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Bookmark {
     pub id: BookmarkId,
     pub title: String,
@@ -27,7 +27,7 @@ pub struct Bookmark {
     pub parent_id: Option<FolderId>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Folder {
     pub id: FolderId,
     pub title: String,